@@ -1,10 +1,9 @@
-use bincode;
+use bytes::BytesMut;
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
-use std::io::prelude::*;
-use std::io::Cursor;
+use tokio_util::codec::{Decoder, Encoder};
 extern crate tickstream;
 
+use tickstream::streams::codec::ChunkCodec;
 use tickstream::streams::Chunk2;
 use tickstream::vendor::binance_ws as bn;
 //
@@ -14,7 +13,7 @@ fn main() {
     let bd1: Vec<bn::BookDepthUpdate> = (100..200)
         .map(|i| bn::BookDepthUpdate {
             event: "BookDepthUpdate".to_owned(),
-            event_time: i * 1000 as u64,
+            event_time: i * 1000,
             symbol: "BTCUSD".to_owned(),
             first_update_id: i + 400,
             last_update_id: i + 500,
@@ -33,7 +32,7 @@ fn main() {
     let bd2: Vec<bn::BookDepthUpdate> = (300..400)
         .map(|i| bn::BookDepthUpdate {
             event: "BookDepthUpdate".to_owned(),
-            event_time: i * 1000 as u64,
+            event_time: i * 1000,
             symbol: "BTCUSD".to_owned(),
             first_update_id: i + 400,
             last_update_id: i + 500,
@@ -52,13 +51,14 @@ fn main() {
     let td1: Vec<bn::Trade> = (200..300)
         .map(|i| bn::Trade {
             event: "Trade".to_owned(),
-            event_time: i * 1000 as u64,
+            event_time: i * 1000,
             symbol: "BTCUSD".to_owned(),
+            trade_id: i,
             price: Decimal::new((i * 500 + 7777) as i64, 6),
             quantity: 1000.into(),
-            buyer: 42,
-            seller: 142,
-            trade_time: i * 1000 as u64,
+            buyer: Some(42),
+            seller: Some(142),
+            trade_time: i * 1000,
             maker: true,
             _ignore: None,
         })
@@ -66,13 +66,14 @@ fn main() {
     let td2: Vec<bn::Trade> = (400..500)
         .map(|i| bn::Trade {
             event: "Trade".to_owned(),
-            event_time: i * 1000 as u64,
+            event_time: i * 1000,
             symbol: "BTCUSD".to_owned(),
+            trade_id: i,
             price: Decimal::new((i * 500 + 7777) as i64, 6),
             quantity: 1000.into(),
-            buyer: 42,
-            seller: 142,
-            trade_time: i * 1000 as u64,
+            buyer: Some(42),
+            seller: Some(142),
+            trade_time: i * 1000,
             maker: true,
             _ignore: None,
         })
@@ -85,29 +86,39 @@ fn main() {
         Chunk2::B(td2),
     ];
 
-    let mut buff = Cursor::new(vec![0; 10000000]);
-    bincode::serialize_into(&mut buff, &chunks).expect("serialize_into");
-    let result: Vec<Chunk2<bn::BookDepthUpdate, bn::Trade>> =
-        bincode::deserialize(buff.get_ref()).expect("deserialize");
+    let mut codec = ChunkCodec::new();
+    let mut buff = BytesMut::new();
+    for chunk in chunks.iter().cloned() {
+        codec.encode(chunk, &mut buff).expect("encode");
+    }
+    let mut result: Vec<Chunk2<bn::BookDepthUpdate, bn::Trade>> = vec![];
+    while let Some(chunk) = codec.decode_eof(&mut buff).expect("decode") {
+        result.push(chunk);
+    }
 
     assert_eq!(chunks, result);
     println!("success!");
 
     /*
-    let book_stream = BookStream<BinanceBase>::start().await?;
-    let trade_stream = TradeStream<BinanceBase>::start().await?;
-    let book_writer = MixedFileSink2<Book, Trade>::(book_stream, trade_stream).await?;
+    let platform = BinancePlatform::default();
+    let book_stream = platform.start_book_stream(&["btcusdt"]).await?;
+    let trade_stream = platform.start_trade_stream(&["btcusdt"]).await?;
+    MixedFileSink2::<BookUpdate, Trade>::create("btcusdt.bin", "binance", CHUNK_LEN)
+        .await?
+        .record(book_stream, trade_stream)
+        .await?;
     */
 }
 
 /* when receiving...
  *
  * {
- *   let read_stream = book_reader(MixedFileSource2<BookStream, TradeStream>::new()?;
- *   while let Some(item) = read_stream.next().await? {
- *      match item {
- *          Either::Left(book) => { ... },
- *          Either::Right(trade) => { ... },
+ *   let read_stream = MixedFileSource2::<BookUpdate, Trade>::open("btcusdt.bin").await?.into_stream();
+ *   pin_mut!(read_stream);
+ *   while let Some(item) = read_stream.next().await {
+ *      match item? {
+ *          Item2::A(book) => { ... },
+ *          Item2::B(trade) => { ... },
  *      }
  *   }
  *
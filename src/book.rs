@@ -0,0 +1,315 @@
+use crate::streams::websockets::WsEvent;
+use crate::types::Error;
+use crate::vendor::binance_ws::{Book, BookDepthUpdate};
+use crate::{BookList, BookUpdate, Price, Quantity};
+use anyhow::Result;
+use async_stream::try_stream;
+use backoff::backoff::Backoff;
+use backoff::{future::retry as backoff_retry, ExponentialBackoff, SystemClock};
+use futures::future::{select, Either, Future};
+use futures::stream::{Stream, StreamExt};
+use futures_util::pin_mut;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+/// An incremental depth update carrying the range of update ids it covers
+pub trait DepthDiff {
+    fn first_update_id(&self) -> u64;
+    fn last_update_id(&self) -> u64;
+    fn bids(&self) -> &BookList;
+    fn asks(&self) -> &BookList;
+}
+
+impl DepthDiff for BookDepthUpdate {
+    fn first_update_id(&self) -> u64 {
+        self.first_update_id
+    }
+    fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+    fn bids(&self) -> &BookList {
+        &self.bids
+    }
+    fn asks(&self) -> &BookList {
+        &self.asks
+    }
+}
+
+impl DepthDiff for BookUpdate {
+    fn first_update_id(&self) -> u64 {
+        self.first_update_id
+    }
+    fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+    fn bids(&self) -> &BookList {
+        &self.bids
+    }
+    fn asks(&self) -> &BookList {
+        &self.asks
+    }
+}
+
+/// A local order book, seeded from a depth snapshot and kept current by
+/// applying diffs in update id order.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OrderBook {
+    last_update_id: u64,
+    bids: BTreeMap<Price, Quantity>,
+    asks: BTreeMap<Price, Quantity>,
+}
+
+impl From<Book> for OrderBook {
+    fn from(book: Book) -> Self {
+        let mut ob = OrderBook {
+            last_update_id: book.last_update_id,
+            ..Default::default()
+        };
+        set_levels(&mut ob.bids, &book.bids);
+        set_levels(&mut ob.asks, &book.asks);
+        ob
+    }
+}
+
+fn set_levels(side: &mut BTreeMap<Price, Quantity>, levels: &[(Price, Quantity)]) {
+    for (price, qty) in levels {
+        if *qty == Decimal::default() {
+            side.remove(price);
+        } else {
+            side.insert(*price, *qty);
+        }
+    }
+}
+
+impl OrderBook {
+    /// Apply a diff, following Binance's rules for managing a local book:
+    /// diffs entirely at or below the current update id are stale and
+    /// dropped (returns `Ok(false)`), a diff must otherwise start no later
+    /// than the update directly after the current one or there is a gap.
+    /// Levels with a quantity of zero are removed.
+    pub fn apply<D: DepthDiff>(&mut self, diff: &D) -> std::result::Result<bool, Error> {
+        if diff.last_update_id() <= self.last_update_id {
+            return Ok(false);
+        }
+        if diff.first_update_id() > self.last_update_id + 1 {
+            return Err(Error::SequenceGap {
+                expected: self.last_update_id + 1,
+                found: diff.first_update_id(),
+            });
+        }
+        set_levels(&mut self.bids, diff.bids());
+        set_levels(&mut self.asks, diff.asks());
+        self.last_update_id = diff.last_update_id();
+        Ok(true)
+    }
+
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    pub fn best_bid(&self) -> Option<(Price, Quantity)> {
+        self.bids.iter().next_back().map(|(p, q)| (*p, *q))
+    }
+
+    pub fn best_ask(&self) -> Option<(Price, Quantity)> {
+        self.asks.iter().next().map(|(p, q)| (*p, *q))
+    }
+
+    /// The best `n` bids, highest price first
+    pub fn bids(&self, n: usize) -> BookList {
+        self.bids.iter().rev().take(n).map(|(p, q)| (*p, *q)).collect()
+    }
+
+    /// The best `n` asks, lowest price first
+    pub fn asks(&self, n: usize) -> BookList {
+        self.asks.iter().take(n).map(|(p, q)| (*p, *q)).collect()
+    }
+
+    pub fn mid_price(&self) -> Option<Price> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some((bid + ask) / Decimal::from(2))
+    }
+
+    pub fn spread(&self) -> Option<Price> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some(ask - bid)
+    }
+
+    /// Set levels without any sequencing, for venues that deliver their
+    /// snapshots and diffs in-band on a single channel
+    pub fn update(&mut self, bids: &[(Price, Quantity)], asks: &[(Price, Quantity)]) {
+        set_levels(&mut self.bids, bids);
+        set_levels(&mut self.asks, asks);
+    }
+
+    /// Drop all but the best `n` levels on each side
+    pub fn truncate(&mut self, n: usize) {
+        while self.bids.len() > n {
+            let worst = *self.bids.keys().next().unwrap();
+            self.bids.remove(&worst);
+        }
+        while self.asks.len() > n {
+            let worst = *self.asks.keys().next_back().unwrap();
+            self.asks.remove(&worst);
+        }
+    }
+
+    /// A snapshot of the `n` best levels on each side
+    pub fn depth(&self, n: usize) -> Book {
+        Book {
+            last_update_id: self.last_update_id,
+            bids: self.bids(n),
+            asks: self.asks(n),
+        }
+    }
+}
+
+/// Run `fut` to completion, buffering the events that arrive meanwhile.
+/// `None` once the events end.
+async fn buffering<S, D, T>(
+    fut: impl Future<Output = T>,
+    events: &mut Pin<&mut S>,
+    buffered: &mut VecDeque<WsEvent<D>>,
+) -> Result<Option<T>>
+where
+    S: Stream<Item = Result<WsEvent<D>>>,
+{
+    pin_mut!(fut);
+    loop {
+        match select(fut.as_mut(), events.next()).await {
+            Either::Left((t, _)) => return Ok(Some(t)),
+            Either::Right((Some(event), _)) => buffered.push_back(event?),
+            Either::Right((None, _)) => return Ok(None),
+        }
+    }
+}
+
+/// Fetch a `snapshot`, retrying per `policy` while the errors are transient
+/// (see `Error::is_transient`) and waiting out rate limits
+async fn fetch_snapshot<F, Fut>(snapshot: &F, policy: &ExponentialBackoff) -> Result<Book>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Book>>,
+{
+    let policy = ExponentialBackoff { clock: SystemClock::default(), ..*policy };
+    backoff_retry(policy, || async {
+        let e = match snapshot().await {
+            Ok(book) => return Ok(book),
+            Err(e) => e,
+        };
+        match e.downcast_ref::<Error>() {
+            Some(Error::RateLimited { retry_after }) => {
+                warn!(?retry_after, "book snapshot rate limited");
+                if let Some(retry_after) = retry_after {
+                    sleep(*retry_after).await;
+                }
+                Err(backoff::Error::Transient(e))
+            }
+            Some(err) if err.is_transient() => {
+                warn!(error = %err, "book snapshot failed, retrying");
+                Err(backoff::Error::Transient(e))
+            }
+            _ => Err(backoff::Error::Permanent(e)),
+        }
+    })
+    .await
+}
+
+/// Keep an `OrderBook` in sync from a subscription of depth diffs and a
+/// REST `snapshot`, as Binance describes: once the first diff is buffered
+/// the snapshot is fetched, diffs already covered by it are dropped and the
+/// rest applied, yielding the book after every applied diff. A sequence gap
+/// or a reconnect fetches a new snapshot.
+///
+/// Snapshots are retried per `policy` while they fail transiently. Resyncs
+/// that don't get the book going again are spaced out per `policy` too, so
+/// a persistent gap doesn't hammer the venue. The book is shared with the
+/// consumer and only copied when a diff is applied while they still hold it.
+pub fn synced_book_stream<S, D, F, Fut>(
+    events: S,
+    snapshot: F,
+    policy: ExponentialBackoff,
+) -> impl Stream<Item = Result<Arc<OrderBook>>>
+where
+    S: Stream<Item = Result<WsEvent<D>>>,
+    D: DepthDiff,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Book>>,
+{
+    try_stream! {
+        pin_mut!(events);
+        let mut buffered = VecDeque::new();
+        // set while resyncs haven't applied a single diff
+        let mut resyncs: Option<ExponentialBackoff> = None;
+        loop {
+            // a snapshot is of no use until there is a diff to follow it
+            loop {
+                match buffered.front() {
+                    Some(WsEvent::Data(_)) => break,
+                    Some(_) => {
+                        buffered.pop_front();
+                    }
+                    None => match events.next().await {
+                        Some(event) => buffered.push_back(event?),
+                        None => return,
+                    },
+                }
+            }
+            if let Some(resyncs) = resyncs.as_mut() {
+                let delay = resyncs.next_backoff().unwrap_or(resyncs.max_interval);
+                debug!(?delay, "delaying book resync");
+                if buffering(sleep(delay), &mut events, &mut buffered).await?.is_none() {
+                    return;
+                }
+            }
+            let snap = match buffering(fetch_snapshot(&snapshot, &policy), &mut events, &mut buffered).await? {
+                Some(snap) => snap?,
+                None => return,
+            };
+            debug!(last_update_id = snap.last_update_id, buffered = buffered.len(), "book snapshot");
+            if resyncs.is_none() {
+                let mut backoff = ExponentialBackoff { clock: SystemClock::default(), ..policy };
+                backoff.reset();
+                resyncs = Some(backoff);
+            }
+            let mut book = Arc::new(OrderBook::from(snap));
+            loop {
+                let event = match buffered.pop_front() {
+                    Some(event) => event,
+                    None => match events.next().await {
+                        Some(event) => event?,
+                        None => return,
+                    },
+                };
+                let diff = match event {
+                    WsEvent::Data(diff) => diff,
+                    WsEvent::Gap => continue,
+                    WsEvent::Reconnected => {
+                        info!("resyncing book after reconnect");
+                        break;
+                    }
+                };
+                match Arc::make_mut(&mut book).apply(&diff) {
+                    Ok(true) => {
+                        resyncs = None;
+                        yield book.clone();
+                    }
+                    Ok(false) => continue,
+                    Err(Error::SequenceGap { expected, found }) => {
+                        warn!(expected, found, "resyncing book after sequence gap");
+                        // still newer than the book, it may follow the next snapshot
+                        buffered.push_front(WsEvent::Data(diff));
+                        break;
+                    }
+                    Err(e) => Err(e)?,
+                }
+            }
+        }
+    }
+}
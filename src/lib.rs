@@ -1,14 +1,20 @@
-#![feature(type_alias_impl_trait)]
-use futures::stream::Stream;
+#![deny(unstable_features)]
+use futures::stream::BoxStream;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
+pub mod book;
+pub mod market;
 pub mod streams;
 pub mod types;
 pub mod vendor;
 
+use book::OrderBook;
+use market::{Candle, MarketEvent, Ticker, Venue};
+use std::sync::Arc;
+use std::time::Duration;
 use streams::StreamDatum;
 pub use vendor::*;
 
@@ -24,14 +30,19 @@ pub struct Trade {
     pub symbol: String,
     pub price: Price,
     pub quantity: Quantity,
-    pub buyer: u32,
-    pub seller: u32,
+    /// order ids, where the venue still sends them
+    pub buyer: Option<u64>,
+    pub seller: Option<u64>,
     pub trade_time: u64,
     pub maker: bool,
 }
 
 impl StreamDatum for Trade {
     const ID: u16 = 500;
+
+    fn event_time(&self) -> Option<u64> {
+        Some(self.event_time)
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
@@ -47,12 +58,52 @@ pub struct BookUpdate {
 
 impl StreamDatum for BookUpdate {
     const ID: u16 = 501;
+
+    fn event_time(&self) -> Option<u64> {
+        Some(self.event_time)
+    }
+}
+
+pub type BookStream = BoxStream<'static, Result<BookUpdate>>;
+pub type TradeStream = BoxStream<'static, Result<Trade>>;
+pub type SyncedBookStream = BoxStream<'static, Result<Arc<OrderBook>>>;
+pub type EventStream = BoxStream<'static, Result<MarketEvent>>;
+pub type TickerStream = BoxStream<'static, Result<Ticker>>;
+pub type AggTradeStream = BoxStream<'static, Result<market::Trade>>;
+pub type CandleStream = BoxStream<'static, Result<Candle>>;
+
+/// Borrow a list of owned instruments, e.g. `Vec<String>`, as the
+/// `&[&str]` `Platform` streams take
+pub fn instruments<S: AsRef<str>>(owned: &[S]) -> Vec<&str> {
+    owned.iter().map(AsRef::as_ref).collect()
 }
 
+/// A venue we can stream market data from. Implementations hold their own
+/// endpoints and config, and are object safe so that several venues can be
+/// driven as `Box<dyn Platform>`.
 #[async_trait]
-trait Platform {
-    type BookStream: Stream<Item = Result<BookUpdate>>;
-    type TradeStream: Stream<Item = Result<Trade>>;
-    async fn start_book_stream(instrument: &'static str) -> Result<Self::BookStream>;
-    async fn start_trade_stream(instrument: &'static str) -> Result<Self::TradeStream>;
+pub trait Platform: Send + Sync {
+    fn venue(&self) -> Venue;
+    /// Book diffs for all `instruments` over a single connection. Owned
+    /// lists can be passed through `instruments`.
+    async fn start_book_stream(&self, instruments: &[&str]) -> Result<BookStream>;
+    /// Trades for all `instruments` over a single connection
+    async fn start_trade_stream(&self, instruments: &[&str]) -> Result<TradeStream>;
+    /// A local book for `instrument`, kept consistent with the venue
+    async fn start_synced_book_stream(&self, instrument: &str) -> Result<SyncedBookStream>;
+    /// Books and trades for all `instruments` as venue neutral events
+    async fn start_event_stream(&self, instruments: &[&str]) -> Result<EventStream>;
+    /// Best bid and ask for all `instruments` over a single connection
+    async fn start_ticker_stream(&self, _instruments: &[&str]) -> Result<TickerStream> {
+        Err(anyhow!("{} has no ticker stream", self.venue()))
+    }
+    /// Trades for all `instruments`, aggregated by taker order and price
+    async fn start_agg_trade_stream(&self, _instruments: &[&str]) -> Result<AggTradeStream> {
+        Err(anyhow!("{} has no aggregate trade stream", self.venue()))
+    }
+    /// Candles of `interval` for all `instruments`, updated while the
+    /// interval is open
+    async fn start_candle_stream(&self, _instruments: &[&str], _interval: Duration) -> Result<CandleStream> {
+        Err(anyhow!("{} has no candle stream", self.venue()))
+    }
 }
@@ -0,0 +1,251 @@
+use crate::streams::StreamDatum;
+use crate::{BookList, Price, Quantity};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Nanoseconds since the epoch
+pub type Nanos = u64;
+
+pub const NANOS_PER_MS: u64 = 1_000_000;
+
+/// For sources that don't timestamp their messages
+pub fn now_ns() -> Nanos {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Venue {
+    Binance,
+    Kraken,
+}
+
+impl fmt::Display for Venue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Venue::Binance => "binance",
+            Venue::Kraken => "kraken",
+        })
+    }
+}
+
+/// Quote assets Binance symbols are commonly quoted in, longest first where
+/// one is the end of another
+const BINANCE_QUOTES: &[&str] = &[
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD", "BIDR", "IDRT", "DAI", "USD", "BTC", "ETH", "BNB", "EUR", "GBP", "TRY",
+    "BRL", "AUD", "RUB", "UAH", "JPY", "XRP", "DOGE", "TRX",
+];
+
+/// Common name of an asset Kraken names differently
+fn canonical_asset(asset: &str) -> String {
+    match asset {
+        "XBT" => "BTC".into(),
+        "XDG" => "DOGE".into(),
+        _ => asset.to_uppercase(),
+    }
+}
+
+/// A pair traded on a venue, by its common asset names and by the symbol
+/// the venue knows it as
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Instrument {
+    pub venue: Venue,
+    /// e.g. `BTC`, also for Kraken's `XBT`
+    pub base: String,
+    pub quote: String,
+    /// e.g. `BTCUSDT` on Binance or `XBT/USD` on Kraken
+    pub symbol: String,
+}
+
+impl Instrument {
+    pub fn new(venue: Venue, base: &str, quote: &str, symbol: &str) -> Self {
+        Instrument {
+            venue,
+            base: canonical_asset(base),
+            quote: canonical_asset(quote),
+            symbol: symbol.into(),
+        }
+    }
+
+    /// The instrument of a venue native `symbol`. Binance symbols aren't
+    /// delimited, so those are split at a common quote asset, or kept whole
+    /// with an empty base and quote when they end in none of them. Take the
+    /// assets from `SymbolInfo::instrument` where those matter.
+    pub fn parse(venue: Venue, symbol: &str) -> Result<Self> {
+        match venue {
+            Venue::Kraken => {
+                let (base, quote) = symbol
+                    .split_once('/')
+                    .ok_or_else(|| anyhow!("can't tell the assets of {} symbol {}", venue, symbol))?;
+                Ok(Instrument::new(venue, base, quote, symbol))
+            }
+            Venue::Binance => {
+                let upper = symbol.to_uppercase();
+                Ok(BINANCE_QUOTES
+                    .iter()
+                    .find(|q| upper.len() > q.len() && upper.ends_with(*q))
+                    .map(|q| symbol.split_at(symbol.len() - q.len()))
+                    .map(|(base, quote)| Instrument::new(venue, base, quote, symbol))
+                    .unwrap_or_else(|| Instrument::unsplit(venue, symbol)))
+            }
+        }
+    }
+
+    /// An instrument known only by its `symbol`
+    pub fn unsplit(venue: Venue, symbol: &str) -> Self {
+        Instrument {
+            venue,
+            base: String::new(),
+            quote: String::new(),
+            symbol: symbol.into(),
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.base.is_empty() {
+            write!(f, "{}@{}", self.symbol, self.venue)
+        } else {
+            write!(f, "{}/{}@{}", self.base, self.quote, self.venue)
+        }
+    }
+}
+
+/// The side of the aggressor of a trade
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Trade {
+    pub instrument: Instrument,
+    pub time: Nanos,
+    pub price: Price,
+    pub quantity: Quantity,
+    pub side: Side,
+    pub trade_id: Option<u64>,
+}
+
+/// Levels that changed, a quantity of zero removes the level
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BookDelta {
+    pub instrument: Instrument,
+    pub time: Nanos,
+    /// the range of update ids covered, for venues that sequence their
+    /// books
+    pub first_update_id: Option<u64>,
+    pub last_update_id: Option<u64>,
+    pub bids: BookList,
+    pub asks: BookList,
+}
+
+/// The whole book, replacing whatever was known
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BookSnapshot {
+    pub instrument: Instrument,
+    pub time: Nanos,
+    pub last_update_id: Option<u64>,
+    pub bids: BookList,
+    pub asks: BookList,
+}
+
+/// Best bid and ask, with the last trade and daily volume where the venue
+/// sends them along
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Ticker {
+    pub instrument: Instrument,
+    pub time: Nanos,
+    pub bid: Price,
+    pub bid_qty: Quantity,
+    pub ask: Price,
+    pub ask_qty: Quantity,
+    pub last: Option<Price>,
+    pub volume_24h: Option<Quantity>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Candle {
+    pub instrument: Instrument,
+    /// when the candle was last updated
+    pub time: Nanos,
+    /// start of the interval
+    pub start: Nanos,
+    /// end of the interval
+    pub end: Nanos,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: Quantity,
+    /// number of trades
+    pub trades: Option<u64>,
+    /// whether the interval is over, otherwise later updates follow
+    pub closed: bool,
+}
+
+/// Best bid and ask
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Spread {
+    pub instrument: Instrument,
+    pub time: Nanos,
+    pub bid: Price,
+    pub bid_qty: Quantity,
+    pub ask: Price,
+    pub ask_qty: Quantity,
+}
+
+/// Market data of any venue
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum MarketEvent {
+    Trade(Trade),
+    BookDelta(BookDelta),
+    BookSnapshot(BookSnapshot),
+    Ticker(Ticker),
+    Candle(Candle),
+    Spread(Spread),
+}
+
+impl MarketEvent {
+    pub fn instrument(&self) -> &Instrument {
+        match self {
+            MarketEvent::Trade(e) => &e.instrument,
+            MarketEvent::BookDelta(e) => &e.instrument,
+            MarketEvent::BookSnapshot(e) => &e.instrument,
+            MarketEvent::Ticker(e) => &e.instrument,
+            MarketEvent::Candle(e) => &e.instrument,
+            MarketEvent::Spread(e) => &e.instrument,
+        }
+    }
+
+    pub fn venue(&self) -> Venue {
+        self.instrument().venue
+    }
+
+    /// When the venue sent it, or when it was received for venues that
+    /// don't say
+    pub fn time(&self) -> Nanos {
+        match self {
+            MarketEvent::Trade(e) => e.time,
+            MarketEvent::BookDelta(e) => e.time,
+            MarketEvent::BookSnapshot(e) => e.time,
+            MarketEvent::Ticker(e) => e.time,
+            MarketEvent::Candle(e) => e.time,
+            MarketEvent::Spread(e) => e.time,
+        }
+    }
+}
+
+impl StreamDatum for MarketEvent {
+    const ID: u16 = 600;
+
+    fn event_time(&self) -> Option<u64> {
+        Some(self.time() / NANOS_PER_MS)
+    }
+}
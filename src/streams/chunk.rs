@@ -0,0 +1,264 @@
+use crate::vendor::binance_ws::{AggregateTrade, Book, BookDepthUpdate, BookTicker, KlineUpdate, Trade as BinanceTrade};
+use crate::{BookUpdate, Trade};
+use super::schema::schema_hash;
+use super::StreamDatum;
+use serde::{de, de::DeserializeOwned, Deserialize, Serialize};
+use std::any::type_name;
+
+#[doc(hidden)]
+pub use serde as __serde;
+
+/// A member type of a chunk, as listed in file headers
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct DatumType {
+    /// `StreamDatum::ID`
+    pub id: u16,
+    pub name: String,
+    /// `schema_hash` of the type
+    pub schema: u64,
+}
+
+impl DatumType {
+    pub fn of<T: StreamDatum + DeserializeOwned>() -> Self {
+        DatumType {
+            id: T::ID,
+            name: type_name::<T>().into(),
+            schema: schema_hash::<T>(),
+        }
+    }
+}
+
+/// A batch of items of one of several `StreamDatum` types. Chunks are
+/// serialized as `[ID, [items..]]` so that a reader knows the type of the
+/// items that follow.
+pub trait Chunk: Serialize + DeserializeOwned + Sized {
+    /// One item of any of the member types
+    type Item;
+
+    /// Every member type
+    fn types() -> Vec<DatumType>;
+    /// The `StreamDatum::ID` of the items in this chunk
+    fn id(&self) -> u16;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// A chunk holding just `item`
+    fn new(item: Self::Item) -> Self;
+    /// Append `item` if it is of the type of this chunk, otherwise hand it
+    /// back
+    fn push(&mut self, item: Self::Item) -> Option<Self::Item>;
+    fn into_items(self) -> Vec<Self::Item>;
+    /// `StreamDatum::event_time` of `item`
+    fn event_time(item: &Self::Item) -> Option<u64>;
+    /// The earliest and latest event time of the items, if any has one
+    fn time_range(&self) -> Option<(u64, u64)>;
+}
+
+/// The earliest and latest of `times`
+pub fn time_range(times: impl Iterator<Item = Option<u64>>) -> Option<(u64, u64)> {
+    times.flatten().fold(None, |range, t| match range {
+        None => Some((t, t)),
+        Some((min, max)) => Some((min.min(t), max.max(t))),
+    })
+}
+
+/// The error for a chunk of a type that isn't one of `types`
+pub fn unknown_id<E: de::Error>(id: u16, types: &[DatumType]) -> E {
+    let known: Vec<String> = types
+        .iter()
+        .map(|t| format!("{} ({})", t.id, t.name))
+        .collect();
+    E::custom(format_args!(
+        "unknown StreamDatum ID {}, expected one of {}",
+        id,
+        known.join(", ")
+    ))
+}
+
+/// Fails the build when two member types of a chunk share an ID
+#[doc(hidden)]
+pub const fn assert_unique_ids(ids: &[u16]) {
+    let mut i = 0;
+    while i < ids.len() {
+        let mut j = i + 1;
+        while j < ids.len() {
+            if ids[i] == ids[j] {
+                panic!("duplicate StreamDatum ID in chunk");
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// Declare a `Chunk` of any number of `StreamDatum` types, along with the
+/// enum of its items:
+///
+/// ```
+/// use tickstream::chunk_enum;
+/// use tickstream::{BookUpdate, Trade};
+///
+/// chunk_enum! {
+///     pub enum NormalizedChunk(Normalized) {
+///         BookUpdate(BookUpdate),
+///         Trade(Trade),
+///     }
+/// }
+/// ```
+///
+/// The member types double as the registry of IDs the chunk decodes,
+/// anything else is rejected with `unknown_id`.
+#[macro_export]
+macro_rules! chunk_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $chunk:ident($item:ident) {
+            $($variant:ident($ty:ty)),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, PartialEq, Debug)]
+        $vis enum $chunk {
+            $($variant(Vec<$ty>)),+
+        }
+
+        #[doc = concat!("A single item of a `", stringify!($chunk), "`")]
+        #[derive(Clone, PartialEq, Debug)]
+        $vis enum $item {
+            $($variant($ty)),+
+        }
+
+        const _: () = $crate::streams::chunk::assert_unique_ids(&[
+            $(<$ty as $crate::streams::StreamDatum>::ID),+
+        ]);
+
+        impl $crate::streams::chunk::Chunk for $chunk {
+            type Item = $item;
+
+            fn types() -> Vec<$crate::streams::chunk::DatumType> {
+                vec![$($crate::streams::chunk::DatumType::of::<$ty>()),+]
+            }
+
+            fn id(&self) -> u16 {
+                match self {
+                    $($chunk::$variant(_) => <$ty as $crate::streams::StreamDatum>::ID),+
+                }
+            }
+
+            fn len(&self) -> usize {
+                match self {
+                    $($chunk::$variant(items) => items.len()),+
+                }
+            }
+
+            fn new(item: $item) -> Self {
+                match item {
+                    $($item::$variant(item) => $chunk::$variant(vec![item])),+
+                }
+            }
+
+            #[allow(unreachable_patterns)]
+            fn push(&mut self, item: $item) -> Option<$item> {
+                match (self, item) {
+                    $(($chunk::$variant(items), $item::$variant(item)) => {
+                        items.push(item);
+                        None
+                    })+
+                    (_, item) => Some(item),
+                }
+            }
+
+            fn into_items(self) -> Vec<$item> {
+                match self {
+                    $($chunk::$variant(items) => items.into_iter().map($item::$variant).collect()),+
+                }
+            }
+
+            fn event_time(item: &$item) -> Option<u64> {
+                match item {
+                    $($item::$variant(item) => <$ty as $crate::streams::StreamDatum>::event_time(item)),+
+                }
+            }
+
+            fn time_range(&self) -> Option<(u64, u64)> {
+                match self {
+                    $($chunk::$variant(items) => $crate::streams::chunk::time_range(
+                        items.iter().map(<$ty as $crate::streams::StreamDatum>::event_time),
+                    )),+
+                }
+            }
+        }
+
+        impl $crate::streams::chunk::__serde::Serialize for $chunk {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: $crate::streams::chunk::__serde::Serializer,
+            {
+                use $crate::streams::chunk::__serde::ser::SerializeSeq;
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                match self {
+                    $($chunk::$variant(items) => {
+                        seq.serialize_element(&<$ty as $crate::streams::StreamDatum>::ID)?;
+                        seq.serialize_element(items)?;
+                    })+
+                }
+                seq.end()
+            }
+        }
+
+        impl<'de> $crate::streams::chunk::__serde::Deserialize<'de> for $chunk {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: $crate::streams::chunk::__serde::Deserializer<'de>,
+            {
+                use $crate::streams::chunk::__serde::de::{self, SeqAccess, Visitor};
+
+                struct ChunkVisitor;
+
+                impl<'de> Visitor<'de> for ChunkVisitor {
+                    type Value = $chunk;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str(concat!("enum ", stringify!($chunk)))
+                    }
+
+                    fn visit_seq<V>(self, mut seq: V) -> Result<$chunk, V::Error>
+                    where
+                        V: SeqAccess<'de>,
+                    {
+                        let type_id: u16 = seq
+                            .next_element()?
+                            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                        $(if type_id == <$ty as $crate::streams::StreamDatum>::ID {
+                            let items: Vec<$ty> = seq
+                                .next_element()?
+                                .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                            return Ok($chunk::$variant(items));
+                        })+
+                        Err($crate::streams::chunk::unknown_id(
+                            type_id,
+                            &<$chunk as $crate::streams::chunk::Chunk>::types(),
+                        ))
+                    }
+                }
+
+                deserializer.deserialize_seq(ChunkVisitor)
+            }
+        }
+    };
+}
+
+chunk_enum! {
+    /// A chunk of any of the types we stream, vendor specific or normalized
+    pub enum AnyChunk(AnyDatum) {
+        BookTicker(BookTicker),
+        BookDepthUpdate(BookDepthUpdate),
+        Book(Book),
+        BinanceTrade(BinanceTrade),
+        AggregateTrade(AggregateTrade),
+        KlineUpdate(KlineUpdate),
+        Trade(Trade),
+        BookUpdate(BookUpdate),
+    }
+}
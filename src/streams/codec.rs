@@ -0,0 +1,188 @@
+use super::chunk::Chunk;
+use super::columnar;
+use super::format::Encoding;
+use crate::types::Error;
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use futures::future::ready;
+use futures::stream::{Stream, StreamExt};
+use std::io::SeekFrom;
+use std::marker::PhantomData;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tokio_util::codec::{Decoder, Encoder, FramedRead};
+use tracing::trace;
+
+/// Default limit on the encoded size of a chunk
+pub const MAX_CHUNK_LEN: usize = 8 * 1024 * 1024;
+const LEN_BYTES: usize = 4;
+
+/// Frames chunks as a u32 BE length followed by their encoding, bincode
+/// unless the codec was made `with_encoding`. Only one frame is buffered at
+/// a time and frames above `max_len`, compressed or not, are refused, so
+/// decoding takes bounded memory however long the input.
+///
+/// A frame cut short at the end of the input fails with
+/// `Error::TruncatedChunk`, whose offset is where the complete frames end
+/// and so where to resume writing.
+#[derive(Debug)]
+pub struct ChunkCodec<C> {
+    max_len: usize,
+    encoding: Encoding,
+    position: u64,
+    _chunk: PhantomData<fn() -> C>,
+}
+
+impl<C> Default for ChunkCodec<C> {
+    fn default() -> Self {
+        ChunkCodec::new()
+    }
+}
+
+impl<C> ChunkCodec<C> {
+    pub fn new() -> Self {
+        ChunkCodec::with_max_len(MAX_CHUNK_LEN)
+    }
+
+    pub fn with_max_len(max_len: usize) -> Self {
+        ChunkCodec {
+            max_len,
+            encoding: Encoding::Bincode,
+            position: 0,
+            _chunk: PhantomData,
+        }
+    }
+
+    pub fn with_encoding(encoding: Encoding) -> Self {
+        ChunkCodec {
+            encoding,
+            ..ChunkCodec::new()
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Bytes of complete frames decoded or encoded so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<C: Chunk> Decoder for ChunkCodec<C> {
+    type Item = C;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<C>> {
+        if src.len() < LEN_BYTES {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        if len > self.max_len {
+            return Err(Error::ChunkTooLong { len, max: self.max_len }.into());
+        }
+        if src.len() < LEN_BYTES + len {
+            src.reserve(LEN_BYTES + len - src.len());
+            return Ok(None);
+        }
+        src.advance(LEN_BYTES);
+        let frame = src.split_to(len);
+        self.position += (LEN_BYTES + len) as u64;
+        let chunk: C = match self.encoding {
+            Encoding::Bincode => bincode::deserialize(&frame)?,
+            Encoding::Columnar => {
+                // lz4_flex allocates whatever size the frame claims
+                if frame.len() < LEN_BYTES {
+                    return Err(anyhow!("compressed chunk of {} bytes is too short", len));
+                }
+                let raw_len = u32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize;
+                if raw_len > self.max_len {
+                    return Err(Error::ChunkTooLong {
+                        len: raw_len,
+                        max: self.max_len,
+                    }
+                    .into());
+                }
+                columnar::decode(&lz4_flex::decompress_size_prepended(&frame)?)?
+            }
+        };
+        trace!(id = chunk.id(), items = chunk.len(), bytes = len, "decoded chunk");
+        Ok(Some(chunk))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<C>> {
+        match self.decode(src)? {
+            Some(chunk) => Ok(Some(chunk)),
+            None if src.is_empty() => Ok(None),
+            None => Err(Error::TruncatedChunk { offset: self.position }.into()),
+        }
+    }
+}
+
+impl<C: Chunk> Encoder<C> for ChunkCodec<C> {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, chunk: C, dst: &mut BytesMut) -> Result<()> {
+        let frame = match self.encoding {
+            Encoding::Bincode => bincode::serialize(&chunk)?,
+            Encoding::Columnar => lz4_flex::compress_prepend_size(&columnar::encode(&chunk)?),
+        };
+        if frame.len() > self.max_len {
+            return Err(Error::ChunkTooLong {
+                len: frame.len(),
+                max: self.max_len,
+            }
+            .into());
+        }
+        dst.reserve(LEN_BYTES + frame.len());
+        dst.put_u32(frame.len() as u32);
+        dst.extend_from_slice(&frame);
+        trace!(id = chunk.id(), items = chunk.len(), bytes = frame.len(), "encoded chunk");
+        self.position += (LEN_BYTES + frame.len()) as u64;
+        Ok(())
+    }
+}
+
+/// Decode the bincode chunks read from `rd` one by one, ending after the
+/// first error
+pub fn read_chunks<C, R>(rd: R) -> impl Stream<Item = Result<C>>
+where
+    C: Chunk,
+    R: AsyncRead,
+{
+    decode_chunks(rd, ChunkCodec::new())
+}
+
+/// `read_chunks` with any codec
+pub fn decode_chunks<C, R>(rd: R, codec: ChunkCodec<C>) -> impl Stream<Item = Result<C>>
+where
+    C: Chunk,
+    R: AsyncRead,
+{
+    FramedRead::new(rd, codec)
+        // the codec is left mid frame after a truncated or corrupt one
+        .scan(false, |failed, chunk: Result<C>| {
+            if *failed {
+                return ready(None);
+            }
+            *failed = chunk.is_err();
+            ready(Some(chunk))
+        })
+}
+
+/// Where the complete frames of `rd` end, scanning only the length
+/// prefixes from the current position up to `end`
+pub async fn complete_len<R>(rd: &mut R, end: u64) -> Result<u64>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let mut pos = rd.seek(SeekFrom::Current(0)).await?;
+    while pos + LEN_BYTES as u64 <= end {
+        let len = rd.read_u32().await? as u64;
+        if pos + LEN_BYTES as u64 + len > end {
+            break;
+        }
+        pos = rd.seek(SeekFrom::Current(len as i64)).await?;
+    }
+    Ok(pos)
+}
@@ -0,0 +1,874 @@
+//! Column oriented encoding of a value, usually a chunk. Every leaf of the
+//! value is appended to a column keyed by its path (field indices, with all
+//! the elements of a sequence sharing one) and its kind, so a chunk of
+//! records becomes one column per field. Then
+//!
+//! * integers are delta encoded as zigzag varints, which makes timestamps
+//!   and update ids a byte or two each
+//! * strings that are all decimal numbers are scale encoded, as scale and a
+//!   delta encoded mantissa, the rest go through a dictionary
+//!
+//! Decoding walks the same paths in the same order, so this works for any
+//! type whose `Serialize` and `Deserialize` agree, as bincode requires.
+
+use serde::de::{
+    self, value::Error as ColumnError, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{
+    self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple, SerializeTupleStruct,
+    SerializeTupleVariant,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+/// Path element of option, enum and sequence tags, all other elements are
+/// field or variant indices
+const TAG: u32 = u32::MAX;
+/// Largest scale of a decimal column, as `rust_decimal`
+const MAX_SCALE: usize = 28;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum Kind {
+    Unsigned,
+    Signed,
+    Float,
+    Str,
+    Bytes,
+}
+
+#[derive(Debug)]
+enum Values {
+    Unsigned(Vec<u64>),
+    Signed(Vec<i64>),
+    Float(Vec<f64>),
+    Str(Vec<String>),
+    Bytes(Vec<Vec<u8>>),
+}
+
+impl Values {
+    fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::Unsigned => Values::Unsigned(vec![]),
+            Kind::Signed => Values::Signed(vec![]),
+            Kind::Float => Values::Float(vec![]),
+            Kind::Str => Values::Str(vec![]),
+            Kind::Bytes => Values::Bytes(vec![]),
+        }
+    }
+}
+
+/// How a column is stored
+#[derive(Serialize, Deserialize, Debug)]
+enum Column {
+    Unsigned(Vec<u8>),
+    Signed(Vec<u8>),
+    Float(Vec<f64>),
+    Decimal { scales: Vec<u8>, mantissas: Vec<u8> },
+    Dictionary { words: Vec<String>, indices: Vec<u8> },
+    Bytes(Vec<Vec<u8>>),
+}
+
+impl Column {
+    fn kind(&self) -> Kind {
+        match self {
+            Column::Unsigned(_) => Kind::Unsigned,
+            Column::Signed(_) => Kind::Signed,
+            Column::Float(_) => Kind::Float,
+            Column::Decimal { .. } | Column::Dictionary { .. } => Kind::Str,
+            Column::Bytes(_) => Kind::Bytes,
+        }
+    }
+
+    fn encode(values: Values) -> Self {
+        match values {
+            Values::Unsigned(vs) => Column::Unsigned(deltas(vs.iter().map(|v| *v as i64))),
+            Values::Signed(vs) => Column::Signed(deltas(vs.iter().copied())),
+            Values::Float(vs) => Column::Float(vs),
+            Values::Str(vs) => match vs.iter().map(|s| parse_decimal(s)).collect::<Option<Vec<_>>>() {
+                Some(decimals) => Column::Decimal {
+                    scales: decimals.iter().map(|(_, scale)| *scale).collect(),
+                    mantissas: deltas(decimals.iter().map(|(mantissa, _)| *mantissa)),
+                },
+                None => {
+                    let mut words: Vec<String> = vec![];
+                    let mut index: HashMap<&str, u64> = HashMap::new();
+                    let mut indices = vec![];
+                    for s in &vs {
+                        let i = *index.entry(s.as_str()).or_insert_with(|| {
+                            words.push(s.clone());
+                            words.len() as u64 - 1
+                        });
+                        put_varint(&mut indices, i);
+                    }
+                    Column::Dictionary { words, indices }
+                }
+            },
+            Values::Bytes(vs) => Column::Bytes(vs),
+        }
+    }
+
+    fn decode(self) -> Result<Values, ColumnError> {
+        Ok(match self {
+            Column::Unsigned(bytes) => Values::Unsigned(undeltas(&bytes)?.into_iter().map(|v| v as u64).collect()),
+            Column::Signed(bytes) => Values::Signed(undeltas(&bytes)?),
+            Column::Float(vs) => Values::Float(vs),
+            Column::Decimal { scales, mantissas } => {
+                let mantissas = undeltas(&mantissas)?;
+                if mantissas.len() != scales.len() {
+                    return Err(de::Error::custom("decimal column lengths differ"));
+                }
+                Values::Str(
+                    mantissas
+                        .into_iter()
+                        .zip(scales)
+                        .map(|(mantissa, scale)| format_decimal(mantissa, scale))
+                        .collect(),
+                )
+            }
+            Column::Dictionary { words, indices } => {
+                let mut rd = &indices[..];
+                let mut vs = vec![];
+                while !rd.is_empty() {
+                    let i = get_varint(&mut rd)? as usize;
+                    vs.push(words.get(i).cloned().ok_or_else(|| de::Error::custom("dictionary index out of range"))?);
+                }
+                Values::Str(vs)
+            }
+            Column::Bytes(vs) => Values::Bytes(vs),
+        })
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn get_varint(rd: &mut &[u8]) -> Result<u64, ColumnError> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (b, rest) = rd.split_first().ok_or_else(|| de::Error::custom("truncated varint"))?;
+        *rd = rest;
+        v |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    Err(de::Error::custom("varint too long"))
+}
+
+/// Zigzag varints of the differences between successive values
+fn deltas(vs: impl Iterator<Item = i64>) -> Vec<u8> {
+    let mut buf = vec![];
+    let mut last = 0i64;
+    for v in vs {
+        let d = v.wrapping_sub(last);
+        put_varint(&mut buf, ((d << 1) ^ (d >> 63)) as u64);
+        last = v;
+    }
+    buf
+}
+
+fn undeltas(mut rd: &[u8]) -> Result<Vec<i64>, ColumnError> {
+    let mut vs = vec![];
+    let mut last = 0i64;
+    while !rd.is_empty() {
+        let z = get_varint(&mut rd)?;
+        last = last.wrapping_add((z >> 1) as i64 ^ -((z & 1) as i64));
+        vs.push(last);
+    }
+    Ok(vs)
+}
+
+/// Mantissa and scale of a decimal string, if formatting them gives back
+/// exactly `s`
+fn parse_decimal(s: &str) -> Option<(i64, u8)> {
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (int, frac) = match digits.find('.') {
+        Some(dot) => (&digits[..dot], &digits[dot + 1..]),
+        None => (digits, ""),
+    };
+    if int.is_empty() || frac.len() > MAX_SCALE || !int.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mantissa: i64 = format!("{}{}", int, frac).parse().ok()?;
+    let mantissa = if neg { -mantissa } else { mantissa };
+    let scale = frac.len() as u8;
+    if format_decimal(mantissa, scale) == s {
+        Some((mantissa, scale))
+    } else {
+        None
+    }
+}
+
+fn format_decimal(mantissa: i64, scale: u8) -> String {
+    let scale = scale as usize;
+    let mut digits = mantissa.unsigned_abs().to_string();
+    if digits.len() <= scale {
+        digits = format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits);
+    }
+    if scale > 0 {
+        digits.insert(digits.len() - scale, '.');
+    }
+    if mantissa < 0 {
+        digits.insert(0, '-');
+    }
+    digits
+}
+
+/// Encode `value` into columns, bincode encoded
+pub fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, ColumnError> {
+    let mut shredder = Shredder::default();
+    value.serialize(&mut shredder)?;
+    let columns: Vec<(Vec<u32>, Column)> = shredder
+        .columns
+        .into_iter()
+        .map(|((path, _), values)| (path, Column::encode(values)))
+        .collect();
+    bincode::serialize(&columns).map_err(ser::Error::custom)
+}
+
+/// Decode a value from the output of `encode`
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ColumnError> {
+    let columns: Vec<(Vec<u32>, Column)> = bincode::deserialize(bytes).map_err(de::Error::custom)?;
+    let mut assembler = Assembler::default();
+    for (path, column) in columns {
+        let kind = column.kind();
+        assembler.columns.insert((path, kind), (column.decode()?, 0));
+    }
+    T::deserialize(&mut assembler)
+}
+
+#[derive(Default)]
+struct Shredder {
+    path: Vec<u32>,
+    columns: BTreeMap<(Vec<u32>, Kind), Values>,
+}
+
+impl Shredder {
+    fn values(&mut self, kind: Kind) -> &mut Values {
+        self.columns
+            .entry((self.path.clone(), kind))
+            .or_insert_with(|| Values::new(kind))
+    }
+
+    fn unsigned(&mut self, v: u64) {
+        if let Values::Unsigned(vs) = self.values(Kind::Unsigned) {
+            vs.push(v);
+        }
+    }
+
+    fn signed(&mut self, v: i64) {
+        if let Values::Signed(vs) = self.values(Kind::Signed) {
+            vs.push(v);
+        }
+    }
+
+    fn tag(&mut self, v: u64) {
+        self.path.push(TAG);
+        self.unsigned(v);
+        self.path.pop();
+    }
+
+    fn at<T: Serialize + ?Sized>(&mut self, index: u32, value: &T) -> Result<(), ColumnError> {
+        self.path.push(index);
+        let res = value.serialize(&mut *self);
+        self.path.pop();
+        res
+    }
+}
+
+/// Serializes the parts of a compound value, at path index `next` or 0 for
+/// sequence elements, `variant` is popped at the end
+struct Compound<'a> {
+    sh: &'a mut Shredder,
+    next: Option<u32>,
+    variant: bool,
+}
+
+impl<'a> Compound<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        let index = match &mut self.next {
+            Some(next) => {
+                *next += 1;
+                *next - 1
+            }
+            None => 0,
+        };
+        self.sh.at(index, value)
+    }
+
+    fn finish(self) -> Result<(), ColumnError> {
+        if self.variant {
+            self.sh.path.pop();
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Serializer for &'a mut Shredder {
+    type Ok = ();
+    type Error = ColumnError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), ColumnError> {
+        self.unsigned(v as u64);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), ColumnError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), ColumnError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), ColumnError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), ColumnError> {
+        self.signed(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), ColumnError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), ColumnError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), ColumnError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), ColumnError> {
+        self.unsigned(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), ColumnError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), ColumnError> {
+        if let Values::Float(vs) = self.values(Kind::Float) {
+            vs.push(v);
+        }
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), ColumnError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), ColumnError> {
+        if let Values::Str(vs) = self.values(Kind::Str) {
+            vs.push(v.into());
+        }
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), ColumnError> {
+        if let Values::Bytes(vs) = self.values(Kind::Bytes) {
+            vs.push(v.into());
+        }
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), ColumnError> {
+        self.tag(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), ColumnError> {
+        self.tag(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), ColumnError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), ColumnError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, index: u32, _variant: &'static str) -> Result<(), ColumnError> {
+        self.tag(index as u64);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), ColumnError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), ColumnError> {
+        self.tag(index as u64);
+        self.at(index, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Compound<'a>, ColumnError> {
+        let len = len.ok_or_else(|| ser::Error::custom("sequences must have a known length"))?;
+        self.tag(len as u64);
+        Ok(Compound { sh: self, next: None, variant: false })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, ColumnError> {
+        Ok(Compound { sh: self, next: Some(0), variant: false })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, ColumnError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, ColumnError> {
+        self.tag(index as u64);
+        self.path.push(index);
+        Ok(Compound { sh: self, next: Some(0), variant: true })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Compound<'a>, ColumnError> {
+        let len = len.ok_or_else(|| ser::Error::custom("maps must have a known length"))?;
+        self.tag(len as u64);
+        Ok(Compound { sh: self, next: None, variant: false })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, ColumnError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, ColumnError> {
+        self.serialize_tuple_variant(name, index, variant, len)
+    }
+}
+
+impl<'a> SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTupleVariant for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ColumnError> {
+        self.sh.at(0, key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ColumnError> {
+        self.sh.at(1, value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeStruct for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeStructVariant for Compound<'a> {
+    type Ok = ();
+    type Error = ColumnError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), ColumnError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), ColumnError> {
+        self.finish()
+    }
+}
+
+#[derive(Default)]
+struct Assembler {
+    path: Vec<u32>,
+    /// the decoded values of each column and how many have been taken
+    columns: HashMap<(Vec<u32>, Kind), (Values, usize)>,
+}
+
+macro_rules! take {
+    ($self:ident, $kind:ident) => {{
+        let path = &$self.path;
+        match $self.columns.get_mut(&(path.clone(), Kind::$kind)) {
+            Some((Values::$kind(vs), pos)) if *pos < vs.len() => {
+                *pos += 1;
+                Ok(std::mem::take(&mut vs[*pos - 1]))
+            }
+            _ => Err(de::Error::custom(format!("{:?} column at {:?} is missing or exhausted", Kind::$kind, path))),
+        }
+    }};
+}
+
+impl Assembler {
+    fn unsigned(&mut self) -> Result<u64, ColumnError> {
+        take!(self, Unsigned)
+    }
+
+    fn signed(&mut self) -> Result<i64, ColumnError> {
+        take!(self, Signed)
+    }
+
+    fn float(&mut self) -> Result<f64, ColumnError> {
+        take!(self, Float)
+    }
+
+    fn string(&mut self) -> Result<String, ColumnError> {
+        take!(self, Str)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, ColumnError> {
+        take!(self, Bytes)
+    }
+
+    fn tag(&mut self) -> Result<u64, ColumnError> {
+        self.path.push(TAG);
+        let tag = self.unsigned();
+        self.path.pop();
+        tag
+    }
+
+    fn at<'de, T: DeserializeSeed<'de>>(&mut self, index: u32, seed: T) -> Result<T::Value, ColumnError> {
+        self.path.push(index);
+        let res = seed.deserialize(&mut *self);
+        self.path.pop();
+        res
+    }
+}
+
+fn narrow<T: TryFrom<V>, V: Copy + std::fmt::Display>(v: V) -> Result<T, ColumnError> {
+    T::try_from(v).map_err(|_| de::Error::custom(format!("{} is out of range", v)))
+}
+
+impl<'de> Deserializer<'de> for &mut Assembler {
+    type Error = ColumnError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, ColumnError> {
+        Err(de::Error::custom("columns aren't self describing"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_bool(self.unsigned()? != 0)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_i8(narrow(self.signed()?)?)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_i16(narrow(self.signed()?)?)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_i32(narrow(self.signed()?)?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_i64(self.signed()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_u8(narrow(self.unsigned()?)?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_u16(narrow(self.unsigned()?)?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_u32(narrow(self.unsigned()?)?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_u64(self.unsigned()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_f32(self.float()? as f32)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_f64(self.float()?)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        let c = self.unsigned()?;
+        visitor.visit_char(
+            std::char::from_u32(narrow(c)?).ok_or_else(|| de::Error::custom(format!("{} isn't a char", c)))?,
+        )
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        match self.tag()? {
+            0 => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ColumnError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        let len = narrow(self.tag()?)?;
+        visitor.visit_seq(Parts { de: self, next: None, left: len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, ColumnError> {
+        visitor.visit_seq(Parts { de: self, next: Some(0), left: len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ColumnError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        let len = narrow(self.tag()?)?;
+        visitor.visit_map(Parts { de: self, next: None, left: len })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ColumnError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ColumnError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ColumnError> {
+        self.deserialize_any(visitor)
+    }
+}
+
+/// The mirror of `Compound`
+struct Parts<'a> {
+    de: &'a mut Assembler,
+    next: Option<u32>,
+    left: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Parts<'a> {
+    type Error = ColumnError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, ColumnError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        let index = match &mut self.next {
+            Some(next) => {
+                *next += 1;
+                *next - 1
+            }
+            None => 0,
+        };
+        self.de.at(index, seed).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Parts<'a> {
+    type Error = ColumnError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ColumnError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        self.de.at(0, seed).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ColumnError> {
+        self.de.at(1, seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for &'a mut Assembler {
+    type Error = ColumnError;
+    type Variant = Variant<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Variant<'a>), ColumnError> {
+        let index: u32 = narrow(self.tag()?)?;
+        let variant = seed.deserialize(IntoDeserializer::<ColumnError>::into_deserializer(index))?;
+        Ok((variant, Variant { de: self, index }))
+    }
+}
+
+struct Variant<'a> {
+    de: &'a mut Assembler,
+    index: u32,
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = ColumnError;
+
+    fn unit_variant(self) -> Result<(), ColumnError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ColumnError> {
+        self.de.at(self.index, seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, ColumnError> {
+        self.de.path.push(self.index);
+        let res = visitor.visit_seq(Parts { de: &mut *self.de, next: Some(0), left: len });
+        self.de.path.pop();
+        res
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ColumnError> {
+        self.tuple_variant(fields.len(), visitor)
+    }
+}
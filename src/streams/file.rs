@@ -0,0 +1,280 @@
+use super::chunk::Chunk;
+use super::codec::{complete_len, decode_chunks, ChunkCodec};
+use super::format::{Encoding, FileHeader};
+use super::index::{IndexEntry, IndexWriter, TimeIndex};
+use super::{Chunk2, Item2, StreamDatum};
+use anyhow::{Context, Result};
+use futures::future::ready;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use futures::SinkExt;
+use futures_util::pin_mut;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::codec::FramedWrite;
+use tracing::{debug, warn};
+
+/// Default maximum number of items per chunk
+pub const CHUNK_LEN: usize = 1000;
+
+/// Records items of a `Chunk` type into one tick file, see `format`. Consecutive items of the
+/// same type are batched into a chunk of up to `chunk_len` items, a chunk
+/// is cut short whenever another type arrives so that the original order
+/// is kept. Every chunk is appended as one frame, see `ChunkCodec`, and
+/// chunks with event times are added to the time index of the file, see
+/// `index`.
+pub struct FileSink<C> {
+    frames: FramedWrite<File, ChunkCodec<C>>,
+    /// offset of the first frame written by this sink
+    start: u64,
+    index: IndexWriter,
+    chunk_len: usize,
+    pending: Option<C>,
+}
+
+impl<C: Chunk> FileSink<C> {
+    /// Append to the file at `path`. A new file gets a header naming
+    /// `vendor`, an existing one must have been written with the same
+    /// layout of the types of `C`. A frame left truncated by an earlier
+    /// writer is dropped and writing resumes after the last complete one,
+    /// the time index is brought up to date.
+    pub async fn create<P: AsRef<Path>>(path: P, vendor: &str, chunk_len: usize) -> Result<Self> {
+        FileSink::create_with_encoding(path, vendor, chunk_len, Encoding::Bincode).await
+    }
+
+    /// `create` writing chunks in `encoding`. An existing file keeps the
+    /// encoding it was created with.
+    pub async fn create_with_encoding<P: AsRef<Path>>(
+        path: P,
+        vendor: &str,
+        chunk_len: usize,
+        encoding: Encoding,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let (header, index) = if file.metadata().await?.len() == 0 {
+            let header = FileHeader::new::<C>(vendor, encoding);
+            file.write_all(&header.encode()?).await?;
+            let index = TimeIndex {
+                created: header.created,
+                entries: vec![],
+            };
+            (header, index)
+        } else {
+            let header = FileHeader::read_from(&mut file)
+                .await
+                .with_context(|| format!("failed to read header of {}", path.display()))?;
+            header.check::<C>()?;
+            let end = file.metadata().await?.len();
+            let complete = complete_len(&mut file, end).await?;
+            if complete < end {
+                warn!(path = %path.display(), bytes = end - complete, "dropping truncated chunk");
+                file.set_len(complete).await?;
+            }
+            let mut index = TimeIndex::read(path).await?.unwrap_or_default();
+            index.entries.retain(|e| e.offset < complete);
+            if index.created != header.created {
+                debug!(path = %path.display(), "rebuilding time index");
+            }
+            index.update::<C>(path).await?;
+            (header, index)
+        };
+        Ok(FileSink {
+            start: file.metadata().await?.len(),
+            index: IndexWriter::create(path, &index).await?,
+            frames: FramedWrite::new(file, ChunkCodec::with_encoding(header.encoding)),
+            chunk_len: chunk_len.max(1),
+            pending: None,
+        })
+    }
+
+    pub async fn send(&mut self, item: C::Item) -> Result<()> {
+        let rejected = match &mut self.pending {
+            Some(chunk) => chunk.push(item),
+            None => Some(item),
+        };
+        if let Some(item) = rejected {
+            self.flush().await?;
+            self.pending = Some(C::new(item));
+        }
+        match &self.pending {
+            Some(chunk) if chunk.len() >= self.chunk_len => self.flush().await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Write out the pending chunk, if any
+    pub async fn flush(&mut self) -> Result<()> {
+        if let Some(chunk) = self.pending.take() {
+            if !chunk.is_empty() {
+                let offset = self.start + self.frames.encoder().position();
+                let times = chunk.time_range();
+                self.frames.send(chunk).await.context("failed to write chunk")?;
+                if let Some((min_time, max_time)) = times {
+                    self.index
+                        .append(IndexEntry {
+                            offset,
+                            min_time,
+                            max_time,
+                        })
+                        .await
+                        .context("failed to write time index")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record `items` until they end. Whatever was received is written out
+    /// before an error from the stream is returned.
+    pub async fn record<S>(mut self, items: S) -> Result<()>
+    where
+        S: Stream<Item = Result<C::Item>>,
+    {
+        pin_mut!(items);
+        let res = async {
+            while let Some(item) = items.next().await {
+                self.send(item?).await?;
+            }
+            Ok(())
+        }
+        .await;
+        self.flush().await?;
+        res
+    }
+}
+
+/// Records two streams interleaved into one file, see `FileSink`
+pub struct MixedFileSink2<TA, TB>(FileSink<Chunk2<TA, TB>>);
+
+impl<TA, TB> MixedFileSink2<TA, TB>
+where
+    TA: StreamDatum + Serialize + for<'a> Deserialize<'a> + fmt::Debug,
+    TB: StreamDatum + Serialize + for<'a> Deserialize<'a> + fmt::Debug,
+{
+    /// Append to the file at `path`, see `FileSink::create`
+    pub async fn create<P: AsRef<Path>>(path: P, vendor: &str, chunk_len: usize) -> Result<Self> {
+        Ok(MixedFileSink2(FileSink::create(path, vendor, chunk_len).await?))
+    }
+
+    pub async fn send_a(&mut self, a: TA) -> Result<()> {
+        self.0.send(Item2::A(a)).await
+    }
+
+    pub async fn send_b(&mut self, b: TB) -> Result<()> {
+        self.0.send(Item2::B(b)).await
+    }
+
+    /// Write out the pending chunk, if any
+    pub async fn flush(&mut self) -> Result<()> {
+        self.0.flush().await
+    }
+
+    /// Record `a` and `b` until both end. Whatever was received is written
+    /// out before an error from either stream is returned.
+    pub async fn record<SA, SB>(self, a: SA, b: SB) -> Result<()>
+    where
+        SA: Stream<Item = Result<TA>>,
+        SB: Stream<Item = Result<TB>>,
+    {
+        self.0
+            .record(stream::select(a.map_ok(Item2::A), b.map_ok(Item2::B)))
+            .await
+    }
+}
+
+/// Reads back a file written by `FileSink`, chunk by chunk
+pub struct FileSource<C, R = File> {
+    header: FileHeader,
+    index: Option<TimeIndex>,
+    rd: R,
+    _chunk: PhantomData<fn() -> C>,
+}
+
+/// Reads back a file written by `MixedFileSink2`
+pub type MixedFileSource2<TA, TB> = FileSource<Chunk2<TA, TB>>;
+
+impl<C: Chunk> FileSource<C> {
+    /// Open the file at `path` along with its time index, if it has one,
+    /// see `FileSource::new`
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut source = FileSource::new(file)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        match TimeIndex::read(path).await? {
+            Some(index) if index.created == source.header.created => source.index = Some(index),
+            Some(_) => warn!(path = %path.display(), "ignoring time index of another file"),
+            None => {}
+        }
+        Ok(source)
+    }
+}
+
+impl<C: Chunk, R: AsyncRead + Unpin> FileSource<C, R> {
+    /// Read the header from `rd`, refusing files written with another
+    /// layout of the types of `C`
+    pub async fn new(mut rd: R) -> Result<Self> {
+        let header = FileHeader::read_from(&mut rd).await?;
+        header.check::<C>()?;
+        Ok(FileSource {
+            header,
+            index: None,
+            rd,
+            _chunk: PhantomData,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn index(&self) -> Option<&TimeIndex> {
+        self.index.as_ref()
+    }
+
+    /// Use `index` as the time index of the file
+    pub fn with_index(mut self, index: TimeIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// The chunks as they were written, ending after the first error
+    pub fn chunks(self) -> impl Stream<Item = Result<C>> {
+        decode_chunks(self.rd, ChunkCodec::with_encoding(self.header.encoding))
+    }
+
+    /// The items in their original order
+    pub fn into_stream(self) -> impl Stream<Item = Result<C::Item>> {
+        self.chunks()
+            .map_ok(|chunk| stream::iter(chunk.into_items().into_iter().map(Ok)))
+            .try_flatten()
+    }
+}
+
+impl<C: Chunk, R: AsyncRead + AsyncSeek + Unpin> FileSource<C, R> {
+    /// The items at or after `time`, reading from the first chunk the time
+    /// index says may hold any, or from the start without an index. Items
+    /// without an event time are kept from there on.
+    pub async fn seek_to(mut self, time: u64) -> Result<impl Stream<Item = Result<C::Item>>> {
+        if let Some(offset) = self.index.as_ref().and_then(|index| index.seek(time)) {
+            self.rd.seek(SeekFrom::Start(offset)).await?;
+        }
+        Ok(self.into_stream().try_filter(move |item| {
+            ready(C::event_time(item).is_none_or(|t| t >= time))
+        }))
+    }
+}
@@ -0,0 +1,119 @@
+use super::chunk::{Chunk, DatumType};
+use crate::types::Error;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Tick files start with `MAGIC`, the format `VERSION` (u16 LE) and the
+/// length (u32 LE) of the bincode encoded rest of the `FileHeader`. The
+/// chunks follow as length prefixed (u32 BE) frames in the `Encoding` of
+/// the file, see `ChunkCodec`.
+///
+/// Version 1 files have no encoding in their header, their chunks are all
+/// bincode.
+pub const MAGIC: [u8; 4] = *b"TICK";
+pub const VERSION: u16 = 2;
+/// Headers only list types, anything longer isn't one
+const MAX_HEADER_LEN: u32 = 1 << 20;
+
+/// How the chunks of a file are encoded
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Encoding {
+    /// bincode, quick to write and read
+    #[default]
+    Bincode,
+    /// `columnar` then LZ4, several times smaller for the cost of a little
+    /// CPU
+    Columnar,
+}
+
+/// What a tick file holds, written once when the file is created
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FileHeader {
+    pub version: u16,
+    /// ms since the epoch
+    pub created: u64,
+    /// where the data was recorded from, e.g. `binance`
+    pub vendor: String,
+    /// the types of the chunk type the file was written with
+    pub types: Vec<DatumType>,
+    pub encoding: Encoding,
+}
+
+impl FileHeader {
+    pub fn new<C: Chunk>(vendor: &str, encoding: Encoding) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        FileHeader {
+            version: VERSION,
+            created,
+            vendor: vendor.into(),
+            types: C::types(),
+            encoding,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let body = bincode::serialize(&(self.created, &self.vendor, &self.types, self.encoding))?;
+        let mut buf = Vec::with_capacity(10 + body.len());
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&self.version.to_le_bytes());
+        buf.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&body);
+        Ok(buf)
+    }
+
+    /// Read a header, refusing versions newer than `VERSION`. Older headers
+    /// are upgraded, `version` is still the one of the file.
+    pub async fn read_from<R: AsyncRead + Unpin>(rd: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        rd.read_exact(&mut magic).await.map_err(|_| Error::BadMagic)?;
+        if magic != MAGIC {
+            return Err(Error::BadMagic.into());
+        }
+        let version = rd.read_u16_le().await?;
+        if version == 0 || version > VERSION {
+            return Err(Error::UnsupportedVersion(version).into());
+        }
+        let len = rd.read_u32_le().await?;
+        if len > MAX_HEADER_LEN {
+            return Err(anyhow!("file header of {} bytes is too long", len));
+        }
+        let mut body = vec![0u8; len as usize];
+        rd.read_exact(&mut body).await?;
+        let (created, vendor, types, encoding) = match version {
+            1 => {
+                let (created, vendor, types) = bincode::deserialize(&body)?;
+                (created, vendor, types, Encoding::Bincode)
+            }
+            _ => bincode::deserialize(&body)?,
+        };
+        Ok(FileHeader {
+            version,
+            created,
+            vendor,
+            types,
+            encoding,
+        })
+    }
+
+    /// Check that the types `C` shares with the file still have the layout
+    /// they were written with. Types only one side knows are fine, chunks of
+    /// those fail to decode on their own.
+    pub fn check<C: Chunk>(&self) -> Result<(), Error> {
+        for ours in C::types() {
+            if let Some(theirs) = self.types.iter().find(|t| t.id == ours.id) {
+                if theirs.schema != ours.schema {
+                    return Err(Error::SchemaMismatch {
+                        id: ours.id,
+                        name: ours.name,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
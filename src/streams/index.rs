@@ -0,0 +1,176 @@
+use super::chunk::Chunk;
+use super::codec::ChunkCodec;
+use super::format::FileHeader;
+use anyhow::{Context, Result};
+use futures::stream::StreamExt;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio_util::codec::FramedRead;
+
+/// Time indexes are kept next to the recording, in `<file>.idx`. They
+/// start with `INDEX_MAGIC` and the `created` time (u64 LE) of the
+/// recording's header, followed by an `IndexEntry` per chunk that has
+/// event times: the offset of its frame in the recording and its earliest
+/// and latest event time, all u64 LE.
+pub const INDEX_MAGIC: [u8; 4] = *b"TIDX";
+const HEADER_LEN: usize = 12;
+const ENTRY_LEN: usize = 24;
+
+/// Where a chunk is and the event times it covers
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub min_time: u64,
+    pub max_time: u64,
+}
+
+impl IndexEntry {
+    fn encode(&self) -> [u8; ENTRY_LEN] {
+        let mut buf = [0u8; ENTRY_LEN];
+        buf[..8].copy_from_slice(&self.offset.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.min_time.to_le_bytes());
+        buf[16..].copy_from_slice(&self.max_time.to_le_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let u64_at = |i: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[i..i + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        IndexEntry {
+            offset: u64_at(0),
+            min_time: u64_at(8),
+            max_time: u64_at(16),
+        }
+    }
+}
+
+/// A sparse index of the chunks of a recording by event time
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct TimeIndex {
+    /// `created` of the header of the recording
+    pub created: u64,
+    /// in file order
+    pub entries: Vec<IndexEntry>,
+}
+
+/// Where the index of the recording at `path` is kept
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".idx");
+    name.into()
+}
+
+impl TimeIndex {
+    /// Read the index of the recording at `path`, `None` if it has none or
+    /// it isn't an index. An entry cut short by a crash is ignored.
+    pub async fn read(path: &Path) -> Result<Option<Self>> {
+        let path = index_path(path);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        if bytes.len() < HEADER_LEN || bytes[..4] != INDEX_MAGIC {
+            return Ok(None);
+        }
+        let mut created = [0u8; 8];
+        created.copy_from_slice(&bytes[4..HEADER_LEN]);
+        Ok(Some(TimeIndex {
+            created: u64::from_le_bytes(created),
+            entries: bytes[HEADER_LEN..]
+                .chunks_exact(ENTRY_LEN)
+                .map(IndexEntry::decode)
+                .collect(),
+        }))
+    }
+
+    /// Index the recording at `path` by decoding all of it
+    pub async fn build<C: Chunk>(path: &Path) -> Result<Self> {
+        let mut index = TimeIndex::default();
+        index.update::<C>(path).await?;
+        Ok(index)
+    }
+
+    /// Add the chunks of the recording at `path` after the last indexed
+    /// one, decoding only those. Writers index a chunk once it is written,
+    /// so a crash in between leaves it out.
+    pub async fn update<C: Chunk>(&mut self, path: &Path) -> Result<()> {
+        let mut file = File::open(path)
+            .await
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let header = FileHeader::read_from(&mut file).await?;
+        header.check::<C>()?;
+        if header.created != self.created {
+            self.created = header.created;
+            self.entries.clear();
+        }
+        let last = self.entries.last().map(|e| e.offset);
+        let start = match last {
+            Some(offset) => file.seek(SeekFrom::Start(offset)).await?,
+            None => file.seek(SeekFrom::Current(0)).await?,
+        };
+        let mut offset = start;
+        let mut frames = FramedRead::new(file, ChunkCodec::<C>::with_encoding(header.encoding));
+        while let Some(chunk) = frames.next().await {
+            match chunk?.time_range() {
+                Some((min_time, max_time)) if Some(offset) != last => self.entries.push(IndexEntry {
+                    offset,
+                    min_time,
+                    max_time,
+                }),
+                _ => {}
+            }
+            offset = start + frames.decoder().position();
+        }
+        Ok(())
+    }
+
+    /// Offset of the first chunk that may hold items at or after `time`,
+    /// the last indexed chunk if none does
+    pub fn seek(&self, time: u64) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|e| e.max_time >= time)
+            .or_else(|| self.entries.last())
+            .map(|e| e.offset)
+    }
+}
+
+/// Appends entries to the index of a recording as its chunks are written
+pub(crate) struct IndexWriter {
+    file: File,
+}
+
+impl IndexWriter {
+    /// Replace the index of the recording at `path` with `index`
+    pub async fn create(path: &Path, index: &TimeIndex) -> Result<Self> {
+        let path = index_path(path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let mut buf = Vec::with_capacity(HEADER_LEN + ENTRY_LEN * index.entries.len());
+        buf.extend_from_slice(&INDEX_MAGIC);
+        buf.extend_from_slice(&index.created.to_le_bytes());
+        for entry in &index.entries {
+            buf.extend_from_slice(&entry.encode());
+        }
+        file.write_all(&buf).await?;
+        file.flush().await?;
+        Ok(IndexWriter { file })
+    }
+
+    pub async fn append(&mut self, entry: IndexEntry) -> Result<()> {
+        self.file.write_all(&entry.encode()).await?;
+        self.file.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::Result;
+use async_stream::{stream, try_stream};
+use futures::stream::{select_all, Stream, StreamExt};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use tracing::warn;
+
+/// Merge streams that are each in event time order into one in event time
+/// order. Items without a time (`time` returns `None`) go out as soon as
+/// they are read, ties go to the earlier stream. Ends after the first
+/// error of any stream.
+pub fn merge_by_time<T, S, F>(streams: Vec<S>, time: F) -> impl Stream<Item = Result<T>>
+where
+    S: Stream<Item = Result<T>> + Unpin,
+    T: Unpin,
+    F: Fn(&T) -> Option<u64>,
+{
+    try_stream! {
+        let mut streams = streams;
+        let mut heads = Vec::with_capacity(streams.len());
+        for stream in streams.iter_mut() {
+            heads.push(stream.next().await.transpose()?);
+        }
+        loop {
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(i, head)| head.as_ref().map(|item| (time(item).unwrap_or(0), i)))
+                .min();
+            let i = match next {
+                Some((_, i)) => i,
+                None => break,
+            };
+            let item = heads[i].take();
+            heads[i] = streams[i].next().await.transpose()?;
+            if let Some(item) = item {
+                yield item;
+            }
+        }
+    }
+}
+
+/// Put a stream whose items are out of event time order by at most
+/// `window` (in the unit of `time`) back in order, e.g. a recording of
+/// several venues written in arrival order. An item is held back until one
+/// `window` later than it is read, items further out of order than that are
+/// yielded as they are read. Items without a time are never held back.
+pub fn reorder_by_time<T, S, F>(stream: S, time: F, window: u64) -> impl Stream<Item = Result<T>>
+where
+    S: Stream<Item = Result<T>> + Unpin,
+    T: Unpin,
+    F: Fn(&T) -> Option<u64>,
+{
+    try_stream! {
+        let mut stream = stream;
+        // by time, then read order for ties
+        let mut held = BTreeMap::new();
+        let mut seq = 0u64;
+        let mut latest = 0;
+        while let Some(item) = stream.next().await {
+            let item = item?;
+            let t = match time(&item) {
+                Some(t) => t,
+                None => {
+                    yield item;
+                    continue;
+                }
+            };
+            held.insert((t, seq), item);
+            seq += 1;
+            latest = latest.max(t);
+            while held.first_key_value().is_some_and(|(&(first, _), _)| first.saturating_add(window) <= latest) {
+                if let Some((_, item)) = held.pop_first() {
+                    yield item;
+                }
+            }
+        }
+        while let Some((_, item)) = held.pop_first() {
+            yield item;
+        }
+    }
+}
+
+/// An item of `merge_ordered`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Merged<T> {
+    /// index of the stream it came from
+    pub source: usize,
+    pub item: T,
+    /// set when an item with a later time was yielded before this one
+    /// arrived, to how much later (ms)
+    pub late_by: Option<u64>,
+}
+
+/// An item held back by `merge_ordered`, the heap's top is the earliest
+struct Pending<T> {
+    time: u64,
+    /// arrival order, for ties
+    seq: u64,
+    due: Instant,
+    source: usize,
+    item: T,
+}
+
+impl<T> PartialEq for Pending<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.seq) == (other.time, other.seq)
+    }
+}
+
+impl<T> Eq for Pending<T> {}
+
+impl<T> PartialOrd for Pending<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Pending<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.time, other.seq).cmp(&(self.time, self.seq))
+    }
+}
+
+/// Merge live streams, e.g. books and trades of several venues, in event
+/// time order. Unlike `select`, whose order depends on which stream happens
+/// to be polled first, every item is held back for `window` after it
+/// arrives so that items of other streams arriving within it can be put
+/// before it. A longer window tolerates more skew between the streams for
+/// more latency.
+///
+/// An item arriving after one with a later time was yielded is yielded
+/// right away with `late_by` set, rather than out of order unannounced.
+/// Items without a time (`time` returns `None`) are never held back. Errors
+/// are passed on as they arrive and the streams keep being merged until
+/// they all end.
+pub fn merge_ordered<T, S, F>(streams: Vec<S>, time: F, window: Duration) -> impl Stream<Item = Result<Merged<T>>>
+where
+    S: Stream<Item = Result<T>>,
+    T: Unpin,
+    F: Fn(&T) -> Option<u64>,
+{
+    let arrivals = select_all(
+        streams
+            .into_iter()
+            .enumerate()
+            .map(|(source, stream)| Box::pin(stream.map(move |item| (source, item)))),
+    );
+    stream! {
+        let mut arrivals = arrivals;
+        let mut pending = BinaryHeap::new();
+        let mut seq = 0u64;
+        // time of the latest item yielded
+        let mut released: Option<u64> = None;
+        let mut open = true;
+        loop {
+            while pending.peek().is_some_and(|p: &Pending<T>| !open || p.due <= Instant::now()) {
+                if let Some(p) = pending.pop() {
+                    released = released.max(Some(p.time));
+                    yield Ok(Merged { source: p.source, item: p.item, late_by: None });
+                }
+            }
+            if !open {
+                break;
+            }
+            let arrival = match pending.peek() {
+                Some(p) => {
+                    let due = p.due;
+                    tokio::select! {
+                        arrival = arrivals.next() => Some(arrival),
+                        _ = sleep_until(due) => None,
+                    }
+                }
+                None => Some(arrivals.next().await),
+            };
+            match arrival {
+                // the earliest pending item is due
+                None => {}
+                Some(None) => open = false,
+                Some(Some((source, Err(e)))) => yield Err(e.context(format!("merged stream {} failed", source))),
+                Some(Some((source, Ok(item)))) => match (time(&item), released) {
+                    (None, _) => yield Ok(Merged { source, item, late_by: None }),
+                    (Some(t), Some(latest)) if t < latest => {
+                        warn!(source, time = t, late_by = latest - t, "late arrival");
+                        yield Ok(Merged { source, item, late_by: Some(latest - t) });
+                    }
+                    (Some(t), _) => {
+                        pending.push(Pending { time: t, seq, due: Instant::now() + window, source, item });
+                        seq += 1;
+                    }
+                },
+            }
+        }
+    }
+}
@@ -5,10 +5,34 @@ use serde::{
 };
 use std::fmt;
 
+pub mod chunk;
+pub mod codec;
+pub mod columnar;
+pub mod file;
+pub mod format;
+pub mod index;
+pub mod merge;
+pub mod replay;
+pub mod schema;
+
+use chunk::{unknown_id, Chunk, DatumType};
 pub mod websockets;
 
 pub trait StreamDatum {
     const ID: u16;
+
+    /// ms since the epoch at which the vendor sent it, for types that have
+    /// one. Recordings are indexed by it, see `index`.
+    fn event_time(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A single item of a stream of two types, see `Chunk2`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Item2<TA, TB> {
+    A(TA),
+    B(TB),
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -64,6 +88,66 @@ impl<TA, TB> Chunk2<TA, TB> {
             B(bb) => bb.len(),
         }
     }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<TA, TB> Chunk for Chunk2<TA, TB>
+where
+    TA: StreamDatum + Serialize + for<'a> Deserialize<'a> + fmt::Debug,
+    TB: StreamDatum + Serialize + for<'a> Deserialize<'a> + fmt::Debug,
+{
+    type Item = Item2<TA, TB>;
+
+    fn types() -> Vec<DatumType> {
+        vec![DatumType::of::<TA>(), DatumType::of::<TB>()]
+    }
+
+    fn id(&self) -> u16 {
+        match self {
+            Chunk2::A(_) => TA::ID,
+            Chunk2::B(_) => TB::ID,
+        }
+    }
+
+    fn len(&self) -> usize {
+        Chunk2::len(self)
+    }
+
+    fn new(item: Item2<TA, TB>) -> Self {
+        match item {
+            Item2::A(a) => Chunk2::A(vec![a]),
+            Item2::B(b) => Chunk2::B(vec![b]),
+        }
+    }
+
+    fn push(&mut self, item: Item2<TA, TB>) -> Option<Item2<TA, TB>> {
+        match (self, item) {
+            (Chunk2::A(aa), Item2::A(a)) => aa.push(a),
+            (Chunk2::B(bb), Item2::B(b)) => bb.push(b),
+            (_, item) => return Some(item),
+        }
+        None
+    }
+
+    fn into_items(self) -> Vec<Item2<TA, TB>> {
+        self.chunk(Item2::A, Item2::B)
+    }
+
+    fn event_time(item: &Item2<TA, TB>) -> Option<u64> {
+        match item {
+            Item2::A(a) => a.event_time(),
+            Item2::B(b) => b.event_time(),
+        }
+    }
+
+    fn time_range(&self) -> Option<(u64, u64)> {
+        match self {
+            Chunk2::A(aa) => chunk::time_range(aa.iter().map(StreamDatum::event_time)),
+            Chunk2::B(bb) => chunk::time_range(bb.iter().map(StreamDatum::event_time)),
+        }
+    }
 }
 
 impl<TA, TB> Serialize for Chunk2<TA, TB>
@@ -93,16 +177,6 @@ where
     }
 }
 
-struct U16Visitor;
-
-impl<'de> Visitor<'de> for U16Visitor {
-    type Value = u16;
-
-    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("Expecting an unsigned 16 bit integer")
-    }
-}
-
 struct Chunk2Visitor<TA, TB> {
     a_: std::marker::PhantomData<TA>,
     b_: std::marker::PhantomData<TB>,
@@ -127,21 +201,17 @@ where
             .next_element()?
             .ok_or_else(|| de::Error::invalid_length(0, &self))?;
         if type_id == TA::ID {
-            println!("found {}", type_id);
             let vals: Vec<TA> = seq
                 .next_element()?
                 .ok_or_else(|| de::Error::invalid_length(1, &self))?;
-            println!("result len={}", vals.len());
             Ok(Chunk2::A(vals))
         } else if type_id == TB::ID {
-            println!("found {}", type_id);
             let vals: Vec<TB> = seq
                 .next_element()?
                 .ok_or_else(|| de::Error::invalid_length(1, &self))?;
-            println!("result len={}", vals.len());
             Ok(Chunk2::B(vals))
         } else {
-            Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &self))
+            Err(unknown_id(type_id, &[DatumType::of::<TA>(), DatumType::of::<TB>()]))
         }
     }
 }
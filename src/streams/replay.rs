@@ -0,0 +1,262 @@
+use super::chunk::Chunk;
+use super::file::MixedFileSource2;
+use super::index::TimeIndex;
+use super::merge::{merge_by_time, reorder_by_time};
+use super::{Chunk2, Item2};
+use crate::market::{self, BookDelta, BookSnapshot, Instrument, MarketEvent, Venue, NANOS_PER_MS};
+use crate::{BookStream, BookUpdate, EventStream, Platform, SyncedBookStream, Trade, TradeStream};
+use crate::vendor::binance_ws::aggressor;
+use anyhow::{anyhow, Context, Result};
+use async_stream::try_stream;
+use async_trait::async_trait;
+use futures::future::ready;
+use futures::stream::{BoxStream, Stream, StreamExt, TryStreamExt};
+use futures_util::pin_mut;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::time::{sleep_until, Instant};
+
+type Recorded = Item2<BookUpdate, Trade>;
+
+/// How far out of event time order a `Replayer` expects recordings to be
+pub const REORDER_WINDOW: Duration = Duration::from_secs(1);
+
+/// How fast a `Replayer` plays back
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Pace {
+    /// as the items were recorded, by their `event_time`
+    RealTime,
+    /// this many times faster than recorded
+    Accelerated(f64),
+    /// as fast as the files can be read
+    Unpaced,
+}
+
+/// A recording and its time index, read when the replayer was opened
+struct Recording {
+    path: PathBuf,
+    index: Option<TimeIndex>,
+}
+
+/// Plays back files recorded by `MixedFileSink2<BookUpdate, Trade>` of a
+/// `venue` as the streams its `Platform` returns, merged across files in
+/// `event_time` order and paced like the live streams were.
+///
+/// A sink records items in the order they arrive, which for streams of
+/// several venues or with different latencies isn't quite event time
+/// order. Items are put back in order within `reorder_window` of each
+/// other, by default `REORDER_WINDOW`.
+///
+/// All streams of one replayer share a clock, which starts at the first
+/// item any of them yields, so book and trade streams stay in step.
+/// Synced books need a snapshot recorded along with the diffs, which the
+/// files don't have, so `start_synced_book_stream` fails.
+pub struct Replayer {
+    venue: Venue,
+    recordings: Vec<Recording>,
+    pace: Pace,
+    from: Option<u64>,
+    reorder_window: Duration,
+    /// the event time and instant playback started at
+    clock: Arc<Mutex<Option<(u64, Instant)>>>,
+}
+
+impl Replayer {
+    /// Check the files at `paths` can be replayed and read their time
+    /// indexes once for all streams
+    pub async fn open<P: AsRef<Path>>(venue: Venue, paths: &[P]) -> Result<Self> {
+        let mut recordings = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref().to_path_buf();
+            let source = MixedFileSource2::<BookUpdate, Trade>::open(&path).await?;
+            recordings.push(Recording {
+                index: source.index().cloned(),
+                path,
+            });
+        }
+        Ok(Replayer {
+            venue,
+            recordings,
+            pace: Pace::RealTime,
+            from: None,
+            reorder_window: REORDER_WINDOW,
+            clock: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Fails for speeds that aren't a finite number above zero
+    pub fn pace(mut self, pace: Pace) -> Result<Self> {
+        if let Pace::Accelerated(speed) = pace {
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err(anyhow!("can't replay at {} times the recorded pace", speed));
+            }
+        }
+        self.pace = pace;
+        Ok(self)
+    }
+
+    /// Skip everything before `time` (ms since the epoch), using the time
+    /// index of the files
+    pub fn starting_at(mut self, time: u64) -> Self {
+        self.from = Some(time);
+        self
+    }
+
+    /// How far out of event time order the recordings may be
+    pub fn reorder_window(mut self, window: Duration) -> Self {
+        self.reorder_window = window;
+        self
+    }
+
+    /// Everything recorded, books and trades interleaved
+    pub async fn replay(&self) -> Result<BoxStream<'static, Result<Recorded>>> {
+        let merged = self.merged().await?;
+        Ok(self.paced(merged, |item: &Recorded| Chunk2::event_time(item).unwrap_or(0)).boxed())
+    }
+
+    async fn merged(&self) -> Result<impl Stream<Item = Result<Recorded>> + Send + 'static> {
+        let window = self.reorder_window.as_millis() as u64;
+        let mut sources = Vec::with_capacity(self.recordings.len());
+        for recording in &self.recordings {
+            let path = &recording.path;
+            let file = File::open(path)
+                .await
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            let mut source = MixedFileSource2::<BookUpdate, Trade>::new(file).await?;
+            if let Some(index) = &recording.index {
+                source = source.with_index(index.clone());
+            }
+            let items = match self.from {
+                Some(time) => source.seek_to(time).await?.boxed(),
+                None => source.into_stream().boxed(),
+            };
+            sources.push(reorder_by_time(items, Chunk2::event_time, window).boxed());
+        }
+        Ok(merge_by_time(sources, Chunk2::event_time))
+    }
+
+    /// Hold each item back until its time has come on the replay clock
+    fn paced<T, S, F>(&self, items: S, time: F) -> impl Stream<Item = Result<T>> + Send + 'static
+    where
+        T: Send + Unpin + 'static,
+        S: Stream<Item = Result<T>> + Send + 'static,
+        F: Fn(&T) -> u64 + Send + 'static,
+    {
+        let speed = match self.pace {
+            Pace::RealTime => Some(1.0),
+            Pace::Accelerated(speed) => Some(speed),
+            Pace::Unpaced => None,
+        };
+        let clock = self.clock.clone();
+        try_stream! {
+            pin_mut!(items);
+            while let Some(item) = items.next().await {
+                let item = item?;
+                if let Some(speed) = speed {
+                    let t = time(&item);
+                    let (start, at) = *clock.lock().unwrap().get_or_insert((t, Instant::now()));
+                    let ms = t.saturating_sub(start) as f64 / speed;
+                    sleep_until(at + Duration::from_secs_f64(ms / 1000.0)).await;
+                }
+                yield item;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Platform for Replayer {
+    fn venue(&self) -> Venue {
+        self.venue
+    }
+
+    /// Book diffs of `instruments`, or of all instruments if none are given
+    async fn start_book_stream(&self, instruments: &[&str]) -> Result<BookStream> {
+        let wanted = symbols(instruments);
+        let books = self.merged().await?.try_filter_map(move |item| {
+            ready(Ok(match item {
+                Item2::A(book) if matches(&wanted, &book.symbol) => Some(book),
+                _ => None,
+            }))
+        });
+        Ok(self.paced(books, |book: &BookUpdate| book.event_time).boxed())
+    }
+
+    /// Trades of `instruments`, or of all instruments if none are given
+    async fn start_trade_stream(&self, instruments: &[&str]) -> Result<TradeStream> {
+        let wanted = symbols(instruments);
+        let trades = self.merged().await?.try_filter_map(move |item| {
+            ready(Ok(match item {
+                Item2::B(trade) if matches(&wanted, &trade.symbol) => Some(trade),
+                _ => None,
+            }))
+        });
+        Ok(self.paced(trades, |trade: &Trade| trade.event_time).boxed())
+    }
+
+    async fn start_synced_book_stream(&self, instrument: &str) -> Result<SyncedBookStream> {
+        Err(anyhow!("recordings have no book snapshots to sync {} against", instrument))
+    }
+
+    /// Books and trades of `instruments` as events, without the trade ids
+    /// the recordings don't keep
+    async fn start_event_stream(&self, instruments: &[&str]) -> Result<EventStream> {
+        let wanted = symbols(instruments);
+        let venue = self.venue;
+        let events = self.merged().await?.try_filter_map(move |item| {
+            ready(match item {
+                Item2::A(book) if matches(&wanted, &book.symbol) => book_event(venue, book).map(Some),
+                Item2::B(trade) if matches(&wanted, &trade.symbol) => trade_event(venue, trade).map(Some),
+                _ => Ok(None),
+            })
+        });
+        Ok(self.paced(events, |e: &MarketEvent| e.time() / NANOS_PER_MS).boxed())
+    }
+}
+
+/// Venues that don't sequence their books record update ids of zero
+fn book_event(venue: Venue, book: BookUpdate) -> Result<MarketEvent> {
+    let instrument = Instrument::parse(venue, &book.symbol)?;
+    let time = book.event_time * NANOS_PER_MS;
+    let id = |id: u64| Some(id).filter(|id| *id != 0);
+    Ok(if book.event == "snapshot" {
+        MarketEvent::BookSnapshot(BookSnapshot {
+            instrument,
+            time,
+            last_update_id: id(book.last_update_id),
+            bids: book.bids,
+            asks: book.asks,
+        })
+    } else {
+        MarketEvent::BookDelta(BookDelta {
+            instrument,
+            time,
+            first_update_id: id(book.first_update_id),
+            last_update_id: id(book.last_update_id),
+            bids: book.bids,
+            asks: book.asks,
+        })
+    })
+}
+
+fn trade_event(venue: Venue, trade: Trade) -> Result<MarketEvent> {
+    Ok(MarketEvent::Trade(market::Trade {
+        instrument: Instrument::parse(venue, &trade.symbol)?,
+        time: trade.trade_time * NANOS_PER_MS,
+        price: trade.price,
+        quantity: trade.quantity,
+        side: aggressor(trade.maker),
+        trade_id: None,
+    }))
+}
+
+fn symbols(instruments: &[&str]) -> Vec<String> {
+    instruments.iter().map(|i| i.to_uppercase()).collect()
+}
+
+/// Vendors differ in the case of symbols they take and send
+fn matches(wanted: &[String], symbol: &str) -> bool {
+    wanted.is_empty() || wanted.iter().any(|w| w.eq_ignore_ascii_case(symbol))
+}
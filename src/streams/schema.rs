@@ -0,0 +1,313 @@
+use serde::de::{
+    self, value::Error as TraceError, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::Deserializer;
+use std::collections::BTreeMap;
+
+/// The layout of `T` as bincode sees it, the types of its fields in order
+/// and recursively. Names aren't part of it as bincode doesn't encode them.
+/// An enum is described by each of its variants' name and layout, as
+/// renaming a variant usually means its meaning changed.
+///
+/// This is traced through `T`'s `Deserialize` impl, a type that needs a
+/// self describing format (`deserialize_any`) is described up to there.
+pub fn schema<T: DeserializeOwned>() -> String {
+    let mut enums = Enums::default();
+    let mut out = String::new();
+    let _ = T::deserialize(Tracer { out: &mut out, enums: &mut enums });
+    // a deserialization only takes one variant of an enum, so every other
+    // one is traced in a pass of its own, inside the variants it was met in
+    while let Some((e, v)) = enums.untraced() {
+        enums.chosen = enums.found[e].within.iter().copied().chain([(e, v)]).collect();
+        let _ = T::deserialize(Tracer { out: &mut String::new(), enums: &mut enums });
+        // a pass that fails before getting there has nothing more to tell
+        enums.found[e].layouts[v].get_or_insert_with(String::new);
+    }
+    let mut schema = String::new();
+    enums.render(&out, &mut schema);
+    schema.trim_end().into()
+}
+
+/// FNV-1a of `schema::<T>()`, stable across builds and platforms
+pub fn schema_hash<T: DeserializeOwned>() -> u64 {
+    schema::<T>()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// The enums met while tracing, referred to as `enum#<index>` in a layout
+/// until they are rendered
+#[derive(Default)]
+struct Enums {
+    found: Vec<Enum>,
+    /// the variant an enum is traced at in this pass, the first if absent
+    chosen: BTreeMap<usize, usize>,
+    /// the enum variants being traced
+    within: Vec<(usize, usize)>,
+}
+
+struct Enum {
+    name: &'static str,
+    variants: &'static [&'static str],
+    /// the enum variants it was first met in
+    within: Vec<(usize, usize)>,
+    layouts: Vec<Option<String>>,
+}
+
+impl Enums {
+    fn find(&mut self, name: &'static str, variants: &'static [&'static str]) -> usize {
+        if let Some(e) = self.found.iter().position(|e| e.name == name && e.variants == variants) {
+            return e;
+        }
+        self.found.push(Enum { name, variants, within: self.within.clone(), layouts: vec![None; variants.len()] });
+        self.found.len() - 1
+    }
+
+    fn untraced(&self) -> Option<(usize, usize)> {
+        self.found.iter().enumerate().find_map(|(e, found)| Some((e, found.layouts.iter().position(Option::is_none)?)))
+    }
+
+    fn render(&self, layout: &str, out: &mut String) {
+        for token in layout.split_whitespace() {
+            let Some(e) = token.strip_prefix("enum#").and_then(|e| e.parse::<usize>().ok()) else {
+                out.push_str(token);
+                out.push(' ');
+                continue;
+            };
+            let e = &self.found[e];
+            out.push_str(&format!("enum{}< ", e.variants.len()));
+            for (v, (name, layout)) in e.variants.iter().zip(&e.layouts).enumerate() {
+                if v > 0 {
+                    out.push_str("| ");
+                }
+                out.push_str(&format!("{name}: "));
+                self.render(layout.as_deref().unwrap_or_default(), out);
+            }
+            out.push_str("> ");
+        }
+    }
+}
+
+struct Tracer<'a> {
+    out: &'a mut String,
+    enums: &'a mut Enums,
+}
+
+macro_rules! trace_primitive {
+    ($($method:ident($name:literal) => $visit:ident($val:expr)),+ $(,)?) => {
+        $(fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+            self.out.push_str(concat!($name, " "));
+            visitor.$visit($val)
+        })+
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for Tracer<'a> {
+    type Error = TraceError;
+
+    trace_primitive! {
+        deserialize_bool("bool") => visit_bool(false),
+        deserialize_i8("i8") => visit_i8(0),
+        deserialize_i16("i16") => visit_i16(0),
+        deserialize_i32("i32") => visit_i32(0),
+        deserialize_i64("i64") => visit_i64(0),
+        deserialize_i128("i128") => visit_i128(0),
+        deserialize_u8("u8") => visit_u8(0),
+        deserialize_u16("u16") => visit_u16(0),
+        deserialize_u32("u32") => visit_u32(0),
+        deserialize_u64("u64") => visit_u64(0),
+        deserialize_u128("u128") => visit_u128(0),
+        deserialize_f32("f32") => visit_f32(0.0),
+        deserialize_f64("f64") => visit_f64(0.0),
+        deserialize_char("char") => visit_char('0'),
+        deserialize_str("str") => visit_str("0"),
+        deserialize_string("str") => visit_str("0"),
+        deserialize_bytes("bytes") => visit_bytes(&[]),
+        deserialize_byte_buf("bytes") => visit_bytes(&[]),
+        deserialize_identifier("u32") => visit_u64(0),
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("any ");
+        Err(de::Error::custom("can't trace a self describing type"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("option< ");
+        let value = visitor.visit_some(Tracer { out: &mut *self.out, enums: &mut *self.enums })?;
+        self.out.push_str("> ");
+        Ok(value)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("unit ");
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("seq< ");
+        let value = visitor.visit_seq(Elements { out: &mut *self.out, enums: &mut *self.enums, left: 1 })?;
+        self.out.push_str("> ");
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("( ");
+        let value = visitor.visit_seq(Elements { out: &mut *self.out, enums: &mut *self.enums, left: len })?;
+        self.out.push_str(") ");
+        Ok(value)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.out.push_str("map< ");
+        let value = visitor.visit_map(Entries { out: &mut *self.out, enums: &mut *self.enums, left: 1 })?;
+        self.out.push_str("> ");
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.out.push_str("{ ");
+        let value = visitor.visit_seq(Elements { out: &mut *self.out, enums: &mut *self.enums, left: fields.len() })?;
+        self.out.push_str("} ");
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let e = self.enums.find(name, variants);
+        if self.enums.within.iter().any(|&(within, _)| within == e) {
+            self.out.push_str("recursive ");
+            return Err(de::Error::custom("can't trace a recursive enum"));
+        }
+        self.out.push_str(&format!("enum#{e} "));
+        let index = self.enums.chosen.get(&e).copied().unwrap_or(0);
+        let mut layout = String::new();
+        self.enums.within.push((e, index));
+        let value = visitor.visit_enum(Variant { out: &mut layout, enums: &mut *self.enums, index });
+        self.enums.within.pop();
+        if let Some(traced) = self.enums.found[e].layouts.get_mut(index) {
+            traced.get_or_insert(layout);
+        }
+        value
+    }
+}
+
+struct Elements<'a> {
+    out: &'a mut String,
+    enums: &'a mut Enums,
+    left: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Elements<'a> {
+    type Error = TraceError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, TraceError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(Tracer { out: &mut *self.out, enums: &mut *self.enums }).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+struct Entries<'a> {
+    out: &'a mut String,
+    enums: &'a mut Enums,
+    left: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for Entries<'a> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, TraceError> {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+        seed.deserialize(Tracer { out: &mut *self.out, enums: &mut *self.enums }).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, TraceError> {
+        seed.deserialize(Tracer { out: &mut *self.out, enums: &mut *self.enums })
+    }
+}
+
+struct Variant<'a> {
+    out: &'a mut String,
+    enums: &'a mut Enums,
+    index: usize,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Variant<'a> {
+    type Error = TraceError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), TraceError> {
+        let variant = seed.deserialize(IntoDeserializer::<TraceError>::into_deserializer(self.index as u32))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Variant<'a> {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<(), TraceError> {
+        self.out.push_str("unit ");
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, TraceError> {
+        seed.deserialize(Tracer { out: self.out, enums: self.enums })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, TraceError> {
+        Tracer { out: self.out, enums: self.enums }.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        Tracer { out: self.out, enums: self.enums }.deserialize_struct("", fields, visitor)
+    }
+}
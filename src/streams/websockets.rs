@@ -1,45 +1,282 @@
 use anyhow::{anyhow, Result, Context};
-use futures::{ stream::Stream, SinkExt, StreamExt };
+use futures::{ future::ready, stream::Stream, Sink, SinkExt, StreamExt };
 use serde::de::DeserializeOwned;
 use serde_json;
 use futures_util::pin_mut;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use async_stream::try_stream;
 use url::Url;
-use backoff::{ ExponentialBackoff, future::retry as backoff_retry };
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::{debug, debug_span, error, field, info, trace, warn};
+use backoff::{ ExponentialBackoff, SystemClock, future::retry as backoff_retry };
 
-pub async fn subscribe<'de, T, U, F>(url: &String, sub_msg: String, translate: F) -> Result<impl Stream<Item = Result<U>>>
+/// Items of a websocket subscription. Besides the data itself consumers are
+/// told when the connection drops, as anything published until the next
+/// `Reconnected` is lost, e.g. book builders need to resync after a `Gap`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WsEvent<U> {
+    Data(U),
+    /// The connection was lost, data may be missing from here on
+    Gap,
+    /// A new connection was made and the subscription replayed
+    Reconnected,
+}
+
+impl<U> WsEvent<U> {
+    pub fn into_data(self) -> Option<U> {
+        match self {
+            WsEvent::Data(u) => Some(u),
+            _ => None,
+        }
+    }
+}
+
+/// Drop the control events from a subscription, leaving the data
+pub fn data_only<U>(events: impl Stream<Item = Result<WsEvent<U>>>) -> impl Stream<Item = Result<U>> {
+    events.filter_map(|e| ready(e.map(WsEvent::into_data).transpose()))
+}
+
+/// Connection settings for `subscribe`
+#[derive(Debug, Default)]
+pub struct WsConfig {
+    /// policy for (re)connecting, the stream fails once it gives up
+    pub backoff: ExponentialBackoff,
+    /// reconnect when no frame at all arrived for this long
+    pub idle_timeout: Option<Duration>,
+}
+
+impl WsConfig {
+    fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff { clock: SystemClock::default(), ..self.backoff }
+    }
+}
+
+// `SystemClock` isn't `Clone` so neither is `ExponentialBackoff`
+impl Clone for WsConfig {
+    fn clone(&self) -> Self {
+        WsConfig {
+            backoff: self.backoff(),
+            idle_timeout: self.idle_timeout,
+        }
+    }
+}
+
+/// How a venue spells its subscriptions. Topics are whatever the venue
+/// subscribes by, e.g. stream names or pairs.
+pub trait SubscriptionProtocol {
+    /// Endpoint to connect to when subscribing to `topics`
+    fn url(&self, topics: &[String]) -> String;
+    /// Messages to send to subscribe to `topics`
+    fn subscribe(&self, topics: &[String]) -> Result<Vec<String>>;
+    /// Messages to send to unsubscribe from `topics`
+    fn unsubscribe(&self, topics: &[String]) -> Result<Vec<String>>;
+}
+
+#[derive(Debug)]
+enum Command {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+/// Changes the topics of a running subscription. The active set is
+/// replayed whenever the connection is re-established.
+#[derive(Clone, Debug)]
+pub struct SubscriptionHandle {
+    topics: Arc<Mutex<Vec<String>>>,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl SubscriptionHandle {
+    pub fn subscribe(&self, topics: &[&str]) -> Result<()> {
+        let mut active = self.topics.lock().unwrap();
+        let added: Vec<String> = topics.iter().filter(|t| !active.iter().any(|a| a == *t)).map(|t| t.to_string()).collect();
+        if !added.is_empty() {
+            active.extend(added.iter().cloned());
+            self.commands.send(Command::Subscribe(added)).map_err(|_| anyhow!("subscription has ended"))?;
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, topics: &[&str]) -> Result<()> {
+        let mut active = self.topics.lock().unwrap();
+        let removed: Vec<String> = active.iter().filter(|a| topics.contains(&a.as_str())).cloned().collect();
+        if !removed.is_empty() {
+            active.retain(|a| !removed.contains(a));
+            self.commands.send(Command::Unsubscribe(removed)).map_err(|_| anyhow!("subscription has ended"))?;
+        }
+        Ok(())
+    }
+
+    /// The currently active topics
+    pub fn topics(&self) -> Vec<String> {
+        self.topics.lock().unwrap().clone()
+    }
+}
+
+/// Connect to the `protocol` endpoint, subscribe to `topics` and yield
+/// every frame decoded as `T` and passed through `translate`. A single
+/// frame may translate into zero or more items, e.g. subscription acks or
+/// batched trades. Frames that fail to decode or translate are logged,
+/// counted and skipped. The returned handle changes the topics at runtime.
+///
+/// Pings are answered by tungstenite. When the connection is lost, closed by the server or
+/// idle for longer than `config.idle_timeout` a `Gap` is yielded and we
+/// reconnect per the `config` backoff policy, resubscribe to the active
+/// topics and yield `Reconnected`.
+pub async fn subscribe<T, U, I, F, P>(
+    protocol: P,
+    topics: Vec<String>,
+    config: WsConfig,
+    translate: F,
+) -> Result<(SubscriptionHandle, impl Stream<Item = Result<WsEvent<U>>>)>
     where
     T: DeserializeOwned + Unpin,
     U: Unpin,
-    F: Fn(&T) -> Result<U>,
+    I: IntoIterator<Item = U>,
+    F: Fn(T) -> Result<I>,
+    P: SubscriptionProtocol,
 {
-    let url : Url = url.parse()?;
+    let _ : Url = protocol.url(&topics).parse()?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let handle = SubscriptionHandle {
+        topics: Arc::new(Mutex::new(topics)),
+        commands: tx,
+    };
+    let active = handle.topics.clone();
+    let subscription = debug_span!("ws_subscription", url = %protocol.url(&handle.topics()), reconnects = 0u64);
     let s = try_stream! {
+        let mut connected = false;
+        let mut reconnects = 0u64;
         loop {
-            let (sock, _resp) = backoff_retry(ExponentialBackoff::default(), || async { Ok(connect_async(&url).await.context("failed to connect to remote WS server")?) }).await?;
+            // changes queued while disconnected are covered by the replay
+            let (url, topics, sub_msgs) = {
+                let active = active.lock().unwrap();
+                while rx.try_recv().is_ok() {}
+                (protocol.url(&active), active.clone(), protocol.subscribe(&active))
+            };
+            let (url, sub_msgs) = (url.parse::<Url>()?, sub_msgs?);
+            let connection = debug_span!(
+                parent: &subscription,
+                "ws_connection",
+                %url,
+                messages = field::Empty,
+                decode_failures = field::Empty,
+            );
+            let (sock, _resp) = backoff_retry(config.backoff(), || async {
+                Ok(connect_async(&url).await.context("failed to connect to remote WS server")?)
+            })
+            .await
+            .map_err(|e| {
+                error!(parent: &connection, error = %e, "giving up connecting");
+                e
+            })?;
             let (mut wr, rd) = sock.split();
-            //let req = serde_json::to_string(&sub_msg)?;
-            wr.send(Message::Text(sub_msg.clone().into())).await.context("failed to send message")?;
-            pin_mut!(rd);
-            while let Some(m) = rd.next().await {
-                let msg = match m? {
-                    Message::Text(txt) => {
-                        let t = serde_json::from_str::<T>(txt.as_ref()).context("failed to deserializes from websockets text message")?;
-                        translate(&t)
-                    },
-                    Message::Binary(bin) => {
-                        let t = serde_json::from_slice::<T>(bin.as_ref()).context("failed to deserialize from websocket bin message")?;
-                        translate(&t)
-                    },
-                    o => {println!("{:?}", o); Err(anyhow!("Unexpected msg: {:?}", o)) }
-                }?;
-                yield msg;
+            let mut messages = 0u64;
+            let mut decode_failures = 0u64;
+            if send_all(&mut wr, sub_msgs).await.is_ok() {
+                debug!(parent: &connection, ?topics, "subscribed");
+                if connected {
+                    reconnects += 1;
+                    subscription.record("reconnects", reconnects);
+                    info!(parent: &connection, reconnects, "reconnected");
+                    yield WsEvent::Reconnected;
+                }
+                connected = true;
+                pin_mut!(rd);
+                loop {
+                    let frame = async {
+                        match config.idle_timeout {
+                            Some(idle) => timeout(idle, rd.next()).await.ok(),
+                            None => Some(rd.next().await),
+                        }
+                    };
+                    let next = tokio::select! {
+                        frame = frame => Ok(frame),
+                        Some(cmd) = rx.recv() => Err(cmd),
+                    };
+                    let next = match next {
+                        Ok(frame) => frame,
+                        Err(cmd) => {
+                            debug!(parent: &connection, ?cmd, "changing subscription");
+                            let msgs = match cmd {
+                                Command::Subscribe(topics) => protocol.subscribe(&topics)?,
+                                Command::Unsubscribe(topics) => protocol.unsubscribe(&topics)?,
+                            };
+                            if send_all(&mut wr, msgs).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    let decoded = match next {
+                        Some(Some(Ok(Message::Text(txt)))) => {
+                            trace!(parent: &connection, len = txt.len(), "text frame");
+                            serde_json::from_str::<T>(txt.as_ref()).context("failed to deserializes from websockets text message")
+                        },
+                        Some(Some(Ok(Message::Binary(bin)))) => {
+                            trace!(parent: &connection, len = bin.len(), "binary frame");
+                            serde_json::from_slice::<T>(bin.as_ref()).context("failed to deserialize from websocket bin message")
+                        },
+                        // tungstenite queues the pong itself and flushes it with the next read
+                        Some(Some(Ok(Message::Ping(_)))) => {
+                            trace!(parent: &connection, "ping");
+                            continue;
+                        },
+                        Some(Some(Ok(Message::Pong(_)))) => continue,
+                        Some(Some(Ok(Message::Close(frame)))) => {
+                            debug!(parent: &connection, ?frame, "closed by the server");
+                            break;
+                        },
+                        Some(Some(Err(e))) => {
+                            debug!(parent: &connection, error = %e, "read failed");
+                            break;
+                        },
+                        Some(None) => {
+                            debug!(parent: &connection, "connection lost");
+                            break;
+                        },
+                        None => {
+                            debug!(parent: &connection, "idle timeout");
+                            break;
+                        },
+                    };
+                    messages += 1;
+                    connection.record("messages", messages);
+                    let msg = match decoded.and_then(&translate) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            decode_failures += 1;
+                            connection.record("decode_failures", decode_failures);
+                            warn!(parent: &connection, error = %e, "failed to decode frame");
+                            continue;
+                        }
+                    };
+                    for item in msg {
+                        yield WsEvent::Data(item);
+                    }
+                }
+            }
+            if connected {
+                warn!(parent: &connection, messages, "disconnected");
+                yield WsEvent::Gap;
             }
         }
     };
 
-    Ok(s)
+    Ok((handle, s))
+}
+
+async fn send_all<S>(wr: &mut S, msgs: Vec<String>) -> Result<(), S::Error>
+where
+    S: Sink<Message> + Unpin,
+{
+    for msg in msgs {
+        wr.send(Message::Text(msg)).await?;
+    }
+    Ok(())
 }
 
 /*
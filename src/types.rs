@@ -1,14 +1,47 @@
-use serde::{de, Deserialize, Serialize};
-use serde_json::{Error as DError, Value};
-use std::collections::BTreeMap;
+use rust_decimal::Decimal;
+use serde_json::Error as DError;
+use std::time::Duration;
 use thiserror::Error;
 use tokio_tungstenite::tungstenite::Error as TungError;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Websockets Error {0}")]
-    WebsocketError(#[from] TungError),
+    WebsocketError(#[from] Box<TungError>),
     #[error("Serde Error {0}")]
     SerdeError(#[from] DError),
+    #[error("HTTP Error {0}")]
+    HttpError(#[from] reqwest::Error),
+    #[error("URL Error {0}")]
+    UrlError(#[from] url::ParseError),
+    #[error("API Error {0:?}")]
+    ApiError(Vec<String>),
+    #[error("Sequence gap, expected update {expected} but got {found}")]
+    SequenceGap { expected: u64, found: u64 },
+    #[error("Not a tickstream file")]
+    BadMagic,
+    #[error("Unsupported file format version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Schema of {name} (ID {id}) differs from the one the file was written with")]
+    SchemaMismatch { id: u16, name: String },
+    #[error("Truncated chunk after byte {offset}")]
+    TruncatedChunk { offset: u64 },
+    #[error("Chunk of {len} bytes exceeds the limit of {max}")]
+    ChunkTooLong { len: usize, max: usize },
+    #[error("{value} has more than the {decimals} decimal places of its pair")]
+    ExcessScale { value: Decimal, decimals: u32 },
+    #[error("Rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
 }
 
+impl Error {
+    /// Whether the same request may succeed if retried later, i.e. the
+    /// venue was rate limiting, unreachable or failing on its end
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::RateLimited { .. } => true,
+            Error::HttpError(e) => e.status().is_none_or(|s| s.is_server_error()),
+            _ => false,
+        }
+    }
+}
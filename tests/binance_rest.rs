@@ -0,0 +1,115 @@
+mod common;
+
+use common::{dec, fixture, mock_http_server, MockResponse};
+use std::time::Duration;
+use tickstream::binance_rest::{BinanceRestClient, KlineInterval, SymbolFilter, WEIGHT_LIMIT};
+use tickstream::market::Venue;
+use tickstream::types::Error;
+
+fn weighted(name: &str, used: u32) -> MockResponse {
+    MockResponse {
+        status: "200 OK",
+        headers: vec![("x-mbx-used-weight-1m", used.to_string())],
+        body: fixture(name),
+    }
+}
+
+#[tokio::test]
+async fn exchange_info_has_symbol_filters() {
+    let (url, seen) = mock_http_server(vec![(
+        "/api/v3/exchangeInfo",
+        weighted("binance/rest/exchange_info.json", 20),
+    )])
+    .await;
+    let client = BinanceRestClient::new(&url).unwrap();
+    assert_eq!(client.weight_limit(), WEIGHT_LIMIT);
+
+    let info = client.exchange_info(&["bnbbtc"]).await.unwrap();
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["/api/v3/exchangeInfo?symbols=%5B%22BNBBTC%22%5D"]
+    );
+    let bnbbtc = &info.symbols[0];
+    assert_eq!(bnbbtc.instrument().to_string(), "BNB/BTC@binance");
+    assert_eq!(bnbbtc.instrument().venue, Venue::Binance);
+    assert_eq!(bnbbtc.tick_size(), Some(dec("0.000001")));
+    assert_eq!(bnbbtc.step_size(), Some(dec("0.01")));
+    assert!(matches!(
+        &bnbbtc.filters[4],
+        SymbolFilter::Other { filter_type } if filter_type == "ICEBERG_PARTS"
+    ));
+    // the weight limit is taken from the rate limits
+    assert_eq!(client.weight_limit(), 1200);
+    assert_eq!(client.used_weight(), 20);
+}
+
+#[tokio::test]
+async fn historical_trades_and_klines() {
+    let (url, seen) = mock_http_server(vec![
+        ("/api/v3/aggTrades", weighted("binance/rest/agg_trades.json", 2)),
+        ("/api/v3/klines", weighted("binance/rest/klines.json", 4)),
+    ])
+    .await;
+    let client = BinanceRestClient::new(&url).unwrap();
+
+    let trades = client.agg_trades("bnbbtc", Some(26129), None, Some(2)).await.unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!((trades[0].id, trades[0].price), (26129, dec("0.01633102")));
+    assert_eq!((trades[1].first_trade_id, trades[1].last_trade_id), (27782, 27784));
+    assert!(trades[0].maker && !trades[1].maker);
+
+    let klines = client
+        .klines("BNBBTC", KlineInterval::W1, Some((1499040000000, 1500249599999)), None)
+        .await
+        .unwrap();
+    assert_eq!(klines.len(), 2);
+    assert_eq!(klines[0].high, dec("0.8"));
+    assert_eq!((klines[0].trades, klines[1].close_time), (308, 1500249599999));
+    assert_eq!(klines[1].taker_buy_quote_volume, dec("7.95"));
+    assert_eq!(client.used_weight(), 4);
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "/api/v3/aggTrades?symbol=BNBBTC&fromId=26129&limit=2",
+            "/api/v3/klines?symbol=BNBBTC&interval=1w&startTime=1499040000000&endTime=1500249599999",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rate_limits_are_errors() {
+    let (url, _) = mock_http_server(vec![(
+        "/api/v3/depth",
+        MockResponse {
+            status: "429 Too Many Requests",
+            headers: vec![("retry-after", "7".into()), ("x-mbx-used-weight-1m", "1250".into())],
+            body: r#"{"code":-1003,"msg":"Too many requests."}"#.into(),
+        },
+    )])
+    .await;
+    let client = BinanceRestClient::new(&url).unwrap();
+
+    match client.depth("BNBBTC", Some(100)).await {
+        Err(Error::RateLimited { retry_after }) => assert_eq!(retry_after, Some(Duration::from_secs(7))),
+        other => panic!("expected a rate limit error, got {:?}", other),
+    }
+    assert_eq!(client.used_weight(), 1250);
+}
+
+#[tokio::test]
+async fn paths_join_under_the_base_path() {
+    let (url, seen) = mock_http_server(vec![(
+        "/binance/api/v3/exchangeInfo",
+        weighted("binance/rest/exchange_info.json", 20),
+    )])
+    .await;
+    for base in [format!("{}/binance", url), format!("{}/binance/", url)] {
+        let client = BinanceRestClient::new(&base).unwrap();
+        client.exchange_info(&["bnbbtc"]).await.unwrap();
+    }
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["/binance/api/v3/exchangeInfo?symbols=%5B%22BNBBTC%22%5D"; 2]
+    );
+}
@@ -0,0 +1,363 @@
+mod common;
+
+use backoff::ExponentialBackoff;
+use common::{accept_ws, dec, fixture_lines, mock_ws_server};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tickstream::binance_ws::{BinancePlatform, BinanceProtocol, StreamRequest};
+use tickstream::market::{Candle, Instrument, Side, Venue};
+use tickstream::streams::websockets::{subscribe, SubscriptionProtocol, WsConfig, WsEvent};
+use tickstream::kraken_ws::KrakenPlatform;
+use tickstream::{instruments, BookUpdate, Platform, Trade as TTrade};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+async fn book_stream_subscribes_and_translates() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/depth.jsonl"),
+    )
+    .await;
+
+    let mut s = BinancePlatform::new(&url, "http://unused")
+        .start_book_stream(&["BNBBTC"])
+        .await
+        .unwrap();
+
+    let first = s.next().await.unwrap().unwrap();
+    let second = s.next().await.unwrap().unwrap();
+
+    let seen = seen.await.unwrap();
+    assert_eq!(seen.uri, "/stream");
+    let req: StreamRequest = serde_json::from_str(&seen.messages[0]).unwrap();
+    assert_eq!(req, StreamRequest::subscribe(vec!["bnbbtc@depth".into()]));
+
+    assert_eq!(
+        first,
+        BookUpdate {
+            event: "depthUpdate".into(),
+            event_time: 1614556800123,
+            symbol: "BNBBTC".into(),
+            first_update_id: 157,
+            last_update_id: 160,
+            bids: vec![(dec("0.0024"), dec("10"))],
+            asks: vec![(dec("0.0026"), dec("100"))],
+        }
+    );
+    assert_eq!(second.first_update_id, 161);
+    assert_eq!(second.last_update_id, 163);
+    assert_eq!(
+        second.bids,
+        vec![(dec("0.0024"), dec("0")), (dec("0.0023"), dec("5.5"))]
+    );
+    assert_eq!(
+        second.asks,
+        vec![(dec("0.0026"), dec("90")), (dec("0.0027"), dec("12.25"))]
+    );
+}
+
+#[tokio::test]
+async fn trade_stream_subscribes_and_translates() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/trade.jsonl"),
+    )
+    .await;
+
+    let s = BinancePlatform::new(&url, "http://unused")
+        .start_trade_stream(&["bnbbtc"])
+        .await
+        .unwrap();
+
+    let trades: Vec<TTrade> = s.take(2).map(|t| t.unwrap()).collect().await;
+
+    let seen = seen.await.unwrap();
+    assert_eq!(seen.uri, "/stream");
+    assert_eq!(
+        seen.messages[0],
+        r#"{"method":"SUBSCRIBE","params":["bnbbtc@trade"],"id":1}"#
+    );
+
+    assert_eq!(
+        trades[0],
+        TTrade {
+            event: "trade".into(),
+            event_time: 1614556800456,
+            symbol: "BNBBTC".into(),
+            price: dec("0.001"),
+            quantity: dec("100"),
+            buyer: Some(88),
+            seller: Some(50),
+            trade_time: 1614556800450,
+            maker: true,
+        }
+    );
+    assert_eq!(trades[1].price, dec("0.00101"));
+    assert_eq!(trades[1].quantity, dec("2.5"));
+    assert!(!trades[1].maker);
+}
+
+#[tokio::test]
+async fn trades_without_order_ids() {
+    let (url, _) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/trade_no_order_ids.jsonl"),
+    )
+    .await;
+
+    let trades: Vec<TTrade> = BinancePlatform::new(&url, "http://unused")
+        .start_trade_stream(&["btcusdt"])
+        .await
+        .unwrap()
+        .take(2)
+        .map(|t| t.unwrap())
+        .collect()
+        .await;
+    assert_eq!((trades[0].buyer, trades[0].seller), (None, None));
+    assert_eq!((trades[1].price, trades[1].quantity), (dec("67512"), dec("0.021")));
+}
+
+#[tokio::test]
+async fn ticker_stream_is_normalized() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/book_ticker.jsonl"),
+    )
+    .await;
+
+    let tickers: Vec<_> = BinancePlatform::new(&url, "http://unused")
+        .start_ticker_stream(&["BNBUSDT"])
+        .await
+        .unwrap()
+        .take(2)
+        .map(|t| t.unwrap())
+        .collect()
+        .await;
+    assert_eq!(seen.await.unwrap().uri, "/stream");
+    assert_eq!(tickers[0].instrument, Instrument::new(Venue::Binance, "BNB", "USDT", "BNBUSDT"));
+    assert_eq!((tickers[0].bid, tickers[0].bid_qty), (dec("25.3519"), dec("31.21")));
+    assert_eq!((tickers[1].ask, tickers[1].ask_qty), (dec("25.3652"), dec("38.1")));
+    assert!(tickers[1].time >= tickers[0].time);
+}
+
+#[tokio::test]
+async fn agg_trade_stream_is_normalized() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/agg_trade.jsonl"),
+    )
+    .await;
+
+    let trades: Vec<_> = BinancePlatform::new(&url, "http://unused")
+        .start_agg_trade_stream(&["bnbbtc"])
+        .await
+        .unwrap()
+        .take(2)
+        .map(|t| t.unwrap())
+        .collect()
+        .await;
+    assert_eq!(seen.await.unwrap().uri, "/stream");
+    assert_eq!(trades[0].trade_id, Some(12345));
+    assert_eq!(trades[0].time, 1614556800450000000);
+    assert_eq!((trades[0].side, trades[1].side), (Side::Sell, Side::Buy));
+    assert_eq!(trades[1].price, dec("0.00101"));
+}
+
+#[tokio::test]
+async fn candle_stream_follows_the_kline() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/kline.jsonl"),
+    )
+    .await;
+    let binance = BinancePlatform::new(&url, "http://unused");
+    assert!(binance.start_candle_stream(&["bnbbtc"], Duration::from_secs(90)).await.is_err());
+
+    let candles: Vec<Candle> = binance
+        .start_candle_stream(&["bnbbtc"], Duration::from_secs(60))
+        .await
+        .unwrap()
+        .take(2)
+        .map(|c| c.unwrap())
+        .collect()
+        .await;
+    assert_eq!(seen.await.unwrap().uri, "/stream");
+    assert_eq!(
+        candles[0],
+        Candle {
+            instrument: Instrument::new(Venue::Binance, "BNB", "BTC", "BNBBTC"),
+            time: 1614556830000000000,
+            start: 1614556800000000000,
+            end: 1614556860000000000,
+            open: dec("0.001"),
+            high: dec("0.0025"),
+            low: dec("0.0015"),
+            close: dec("0.002"),
+            volume: dec("1000"),
+            trades: Some(101),
+            closed: false,
+        }
+    );
+    assert!(candles[1].closed);
+    assert_eq!((candles[1].close, candles[1].trades), (dec("0.0021"), Some(111)));
+}
+
+/// Frames of `name` for `symbol` instead of the recorded one
+fn fixture_for(name: &str, recorded: &str, symbol: &str) -> Vec<String> {
+    fixture_lines(name)
+        .iter()
+        .map(|l| {
+            l.replace(recorded, symbol)
+                .replace(&recorded.to_lowercase(), &symbol.to_lowercase())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn symbols_quoted_in_any_asset_stream() {
+    let reply = vec![r#"{"result":null,"id":1}"#.to_string()];
+    let btcpln = Instrument::unsplit(Venue::Binance, "BTCPLN");
+
+    let (url, _) = mock_ws_server(1, reply.clone(), fixture_for("binance/book_ticker.jsonl", "BNBUSDT", "BTCPLN")).await;
+    let tickers: Vec<_> = BinancePlatform::new(&url, "http://unused")
+        .start_ticker_stream(&["btcpln"])
+        .await
+        .unwrap()
+        .take(2)
+        .map(|t| t.unwrap())
+        .collect()
+        .await;
+    assert_eq!(tickers[1].instrument, btcpln);
+
+    let (url, _) = mock_ws_server(1, reply.clone(), fixture_for("binance/agg_trade.jsonl", "BNBBTC", "BTCPLN")).await;
+    let trades: Vec<_> = BinancePlatform::new(&url, "http://unused")
+        .start_agg_trade_stream(&["btcpln"])
+        .await
+        .unwrap()
+        .take(2)
+        .map(|t| t.unwrap())
+        .collect()
+        .await;
+    assert_eq!(trades[1].instrument, btcpln);
+
+    let (url, _) = mock_ws_server(1, reply, fixture_for("binance/kline.jsonl", "BNBBTC", "BTCPLN")).await;
+    let candles: Vec<_> = BinancePlatform::new(&url, "http://unused")
+        .start_candle_stream(&["btcpln"], Duration::from_secs(60))
+        .await
+        .unwrap()
+        .take(2)
+        .map(|c| c.unwrap())
+        .collect()
+        .await;
+    assert_eq!(candles[1].instrument, btcpln);
+    assert!(candles[1].closed);
+}
+
+#[tokio::test]
+async fn one_connection_for_many_instruments() {
+    let (url, seen) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/trade.jsonl"),
+    )
+    .await;
+    let owned: Vec<String> = vec!["BNBBTC".into(), "ETHBTC".into()];
+
+    let platforms: Vec<Box<dyn Platform>> = vec![
+        Box::new(BinancePlatform::new(&url, "http://unused")),
+        Box::new(KrakenPlatform::default()),
+    ];
+    let mut s = platforms[0].start_trade_stream(&instruments(&owned)).await.unwrap();
+    assert_eq!(s.next().await.unwrap().unwrap().symbol, "BNBBTC");
+
+    let seen = seen.await.unwrap();
+    assert_eq!(seen.uri, "/stream");
+    let req: StreamRequest = serde_json::from_str(&seen.messages[0]).unwrap();
+    assert_eq!(req.params, vec!["bnbbtc@trade", "ethbtc@trade"]);
+}
+
+#[test]
+fn protocol_subscribes_by_stream_name() {
+    let protocol = BinanceProtocol {
+        base_url: "wss://stream.binance.com:9443/".into(),
+    };
+    let streams = vec!["bnbbtc@depth".to_string(), "ethbtc@trade".to_string()];
+
+    assert_eq!(
+        protocol.url(&streams),
+        "wss://stream.binance.com:9443/stream"
+    );
+    let msgs = protocol.unsubscribe(&streams[1..]).unwrap();
+    let req: StreamRequest = serde_json::from_str(&msgs[0]).unwrap();
+    assert_eq!(req, StreamRequest::unsubscribe(vec!["ethbtc@trade".into()]));
+    assert_eq!(req.method, "UNSUBSCRIBE");
+    assert!(protocol.subscribe(&[]).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribes_once_per_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let mut sessions = vec![];
+        for n in 1..=2 {
+            let (uri, mut ws) = accept_ws(&listener).await;
+            let mut frames = vec![ws.next().await.unwrap().unwrap()];
+            ws.send(Message::Text(format!(r#"{{"n":{}}}"#, n))).await.unwrap();
+            if n == 1 {
+                frames.push(ws.next().await.unwrap().unwrap());
+                ws.close(None).await.unwrap();
+            }
+            while let Some(Ok(_)) = ws.next().await {}
+            sessions.push((uri, frames));
+        }
+        sessions
+    });
+
+    let config = WsConfig {
+        backoff: ExponentialBackoff {
+            initial_interval: Duration::from_millis(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let protocol = BinanceProtocol { base_url: url };
+    let streams = vec!["bnbbtc@trade".to_string(), "ethbtc@trade".to_string()];
+    let (handle, s) = subscribe(protocol, streams, config, |v: Value| anyhow::Result::Ok(Some(v)))
+        .await
+        .unwrap();
+    let mut s = Box::pin(s);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 1})));
+    handle.unsubscribe(&["bnbbtc@trade", "ethbtc@trade"]).unwrap();
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Gap);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Reconnected);
+    handle.subscribe(&["bnbbtc@trade"]).unwrap();
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 2})));
+    drop(s);
+
+    let text = |m: &str| Message::Text(m.into());
+    assert_eq!(
+        server.await.unwrap(),
+        vec![
+            (
+                "/stream".to_string(),
+                vec![
+                    text(r#"{"method":"SUBSCRIBE","params":["bnbbtc@trade","ethbtc@trade"],"id":1}"#),
+                    text(r#"{"method":"UNSUBSCRIBE","params":["bnbbtc@trade","ethbtc@trade"],"id":1}"#),
+                ]
+            ),
+            (
+                "/stream".to_string(),
+                vec![text(r#"{"method":"SUBSCRIBE","params":["bnbbtc@trade"],"id":1}"#)]
+            ),
+        ]
+    );
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::dec;
+use rust_decimal::Decimal;
+use tickstream::binance_ws::{Book, BookDepthUpdate};
+use tickstream::book::OrderBook;
+use tickstream::types::Error;
+
+fn levels(l: &[(&str, &str)]) -> Vec<(Decimal, Decimal)> {
+    l.iter().map(|(p, q)| (dec(p), dec(q))).collect()
+}
+
+fn diff(first: u64, last: u64, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> BookDepthUpdate {
+    BookDepthUpdate {
+        event: "depthUpdate".into(),
+        event_time: last * 10,
+        symbol: "BNBBTC".into(),
+        first_update_id: first,
+        last_update_id: last,
+        bids: levels(bids),
+        asks: levels(asks),
+    }
+}
+
+fn book() -> OrderBook {
+    OrderBook::from(Book {
+        last_update_id: 160,
+        bids: levels(&[("0.0024", "10"), ("0.0022", "5"), ("0.0023", "1")]),
+        asks: levels(&[("0.0026", "100"), ("0.0028", "2"), ("0.0027", "7")]),
+    })
+}
+
+#[test]
+fn snapshot_views() {
+    let ob = book();
+    assert_eq!(ob.last_update_id(), 160);
+    assert_eq!(ob.best_bid(), Some((dec("0.0024"), dec("10"))));
+    assert_eq!(ob.best_ask(), Some((dec("0.0026"), dec("100"))));
+    assert_eq!(ob.bids(2), levels(&[("0.0024", "10"), ("0.0023", "1")]));
+    assert_eq!(ob.asks(5), levels(&[("0.0026", "100"), ("0.0027", "7"), ("0.0028", "2")]));
+    assert_eq!(ob.mid_price(), Some(dec("0.0025")));
+    assert_eq!(ob.spread(), Some(dec("0.0002")));
+    assert_eq!(ob.depth(1).asks, levels(&[("0.0026", "100")]));
+    assert_eq!(OrderBook::default().mid_price(), None);
+}
+
+#[test]
+fn applies_diffs_in_sequence() {
+    let mut ob = book();
+
+    // entirely before the snapshot
+    assert!(!ob.apply(&diff(150, 160, &[("0.0024", "0")], &[])).unwrap());
+    assert_eq!(ob.best_bid(), Some((dec("0.0024"), dec("10"))));
+
+    // straddles the snapshot
+    assert!(ob.apply(&diff(158, 162, &[("0.0024", "0")], &[("0.0025", "3")])).unwrap());
+    assert_eq!(ob.last_update_id(), 162);
+    assert_eq!(ob.best_bid(), Some((dec("0.0023"), dec("1"))));
+    assert_eq!(ob.best_ask(), Some((dec("0.0025"), dec("3"))));
+
+    assert!(ob.apply(&diff(163, 165, &[("0.0023", "4.5")], &[("0.0025", "0.000")])).unwrap());
+    assert_eq!(ob.bids(1), levels(&[("0.0023", "4.5")]));
+    assert_eq!(ob.best_ask(), Some((dec("0.0026"), dec("100"))));
+}
+
+#[test]
+fn detects_gaps() {
+    let mut ob = book();
+    match ob.apply(&diff(162, 170, &[("0.0024", "0")], &[])) {
+        Err(Error::SequenceGap { expected, found }) => {
+            assert_eq!(expected, 161);
+            assert_eq!(found, 162);
+        }
+        other => panic!("expected a gap, got {:?}", other),
+    }
+    // the book is left untouched
+    assert_eq!(ob, book());
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use backoff::ExponentialBackoff;
+use common::{dec, fixture, fixture_lines, mock_http_server, mock_ws_server, mock_ws_sessions};
+use futures::{stream, StreamExt};
+use futures_util::pin_mut;
+use rust_decimal::Decimal;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tickstream::binance_ws::{BinancePlatform, Book, BookDepthUpdate, StreamMessage};
+use tickstream::book::{synced_book_stream, OrderBook};
+use tickstream::kraken_ws::KrakenPlatform;
+use tickstream::streams::websockets::WsEvent;
+use tickstream::types::Error;
+use tickstream::Platform;
+
+fn levels(l: &[(&str, &str)]) -> Vec<(Decimal, Decimal)> {
+    l.iter().map(|(p, q)| (dec(p), dec(q))).collect()
+}
+
+/// The recorded diffs of `depth_sync.jsonl`
+fn diffs() -> Vec<anyhow::Result<WsEvent<BookDepthUpdate>>> {
+    fixture_lines("binance/depth_sync.jsonl")
+        .iter()
+        .map(|l| {
+            let msg: StreamMessage<BookDepthUpdate> = serde_json::from_str(l).unwrap();
+            Ok(WsEvent::Data(msg.data.unwrap()))
+        })
+        .collect()
+}
+
+fn snapshot_160() -> Book {
+    serde_json::from_str(&fixture("binance/depth_snapshot_160.json")).unwrap()
+}
+
+fn fast_policy() -> ExponentialBackoff {
+    ExponentialBackoff {
+        initial_interval: Duration::from_millis(20),
+        randomization_factor: 0.0,
+        max_elapsed_time: None,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn binance_drops_stale_diffs_and_resyncs_on_gaps() {
+    let (ws_url, _) = mock_ws_server(
+        1,
+        vec![r#"{"result":null,"id":1}"#.into()],
+        fixture_lines("binance/depth_sync.jsonl"),
+    )
+    .await;
+    let (rest_url, requests) = mock_http_server(vec![
+        ("/api/v3/depth", fixture("binance/depth_snapshot_160.json")),
+        ("/api/v3/depth", fixture("binance/depth_snapshot_171.json")),
+    ])
+    .await;
+
+    let s = BinancePlatform::new(&ws_url, &rest_url)
+        .start_synced_book_stream("bnbbtc")
+        .await
+        .unwrap();
+    let books: Vec<Arc<OrderBook>> = s.take(4).map(|b| b.unwrap()).collect().await;
+
+    // 157..=160 is covered by the first snapshot
+    assert_eq!(books[0].last_update_id(), 163);
+    assert_eq!(books[0].bids(5), levels(&[("0.0023", "5.5")]));
+    assert_eq!(books[0].asks(5), levels(&[("0.0026", "90"), ("0.0027", "10")]));
+    assert_eq!(books[1].last_update_id(), 165);
+    assert_eq!(books[1].bids(5), levels(&[("0.0023", "5.5"), ("0.0022", "1")]));
+    // 170 doesn't follow 165, so the book is rebuilt from the second
+    // snapshot, which 170..=172 does follow
+    assert_eq!(books[2].last_update_id(), 172);
+    assert_eq!(books[2].asks(5), levels(&[("0.0026", "80"), ("0.0027", "10")]));
+    assert_eq!(books[3].last_update_id(), 174);
+    assert_eq!(books[3].bids(5), levels(&[("0.0023", "7"), ("0.0022", "1")]));
+    assert_eq!(books[3].asks(5), levels(&[("0.0027", "10")]));
+
+    assert_eq!(
+        *requests.lock().unwrap(),
+        vec![
+            "/api/v3/depth?symbol=BNBBTC&limit=1000",
+            "/api/v3/depth?symbol=BNBBTC&limit=1000"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn kraken_builds_from_in_band_snapshots() {
+    let (url, seen) = mock_ws_server(1, vec![], fixture_lines("kraken/book.jsonl")).await;
+
+    let platform = KrakenPlatform {
+        book_depth: 2,
+        ..KrakenPlatform::new(&url)
+    };
+    let s = platform.start_synced_book_stream("XBT/USD").await.unwrap();
+    let books: Vec<Arc<OrderBook>> = s.take(3).map(|b| b.unwrap()).collect().await;
+
+    let seen = seen.await.unwrap();
+    assert!(seen.messages[0].contains(r#""subscription":{"name":"book","depth":2}"#));
+
+    assert_eq!(books[0].best_bid(), Some((dec("5541.2"), dec("1.529"))));
+    assert_eq!(books[0].best_ask(), Some((dec("5541.3"), dec("2.507"))));
+    // 5542.5 falls outside the subscribed depth
+    assert_eq!(books[1].asks(5), levels(&[("5541.3", "2.507"), ("5541.8", "0.33")]));
+    assert_eq!(books[2].asks(5), levels(&[("5541.8", "0.33")]));
+    assert_eq!(books[2].spread(), Some(dec("0.6")));
+}
+
+#[tokio::test]
+async fn binance_resyncs_after_reconnecting() {
+    let frames = fixture_lines("binance/depth_sync.jsonl");
+    let ack = r#"{"result":null,"id":1}"#.to_string();
+    let (ws_url, _) = mock_ws_sessions(
+        1,
+        vec![
+            vec![ack.clone(), frames[0].clone(), frames[1].clone()],
+            // 172..=174 directly follows the second snapshot
+            vec![ack, frames[4].clone()],
+        ],
+    )
+    .await;
+    let (rest_url, requests) = mock_http_server(vec![
+        ("/api/v3/depth", fixture("binance/depth_snapshot_160.json")),
+        ("/api/v3/depth", fixture("binance/depth_snapshot_171.json")),
+    ])
+    .await;
+
+    let mut platform = BinancePlatform::new(&ws_url, &rest_url);
+    platform.ws_config.backoff.initial_interval = Duration::from_millis(10);
+    let s = platform.start_synced_book_stream("bnbbtc").await.unwrap();
+    let books: Vec<Arc<OrderBook>> = s.take(2).map(|b| b.unwrap()).collect().await;
+
+    assert_eq!(books[0].last_update_id(), 163);
+    assert_eq!(books[1].last_update_id(), 174);
+    assert_eq!(books[1].asks(5), levels(&[("0.0027", "10")]));
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn snapshots_follow_the_first_diff_and_outlast_rate_limits() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let snapshot = {
+        let calls = calls.clone();
+        move || {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match n {
+                    0 => Err(Error::RateLimited {
+                        retry_after: Some(Duration::from_millis(10)),
+                    })?,
+                    _ => Ok(snapshot_160()),
+                }
+            }
+        }
+    };
+
+    let nothing = stream::pending::<anyhow::Result<WsEvent<BookDepthUpdate>>>();
+    let s = synced_book_stream(nothing, snapshot.clone(), fast_policy());
+    pin_mut!(s);
+    assert!(tokio::time::timeout(Duration::from_millis(50), s.next()).await.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+    let events = stream::iter(diffs().into_iter().take(2)).chain(stream::pending());
+    let s = synced_book_stream(events, snapshot, fast_policy());
+    pin_mut!(s);
+    let book = s.next().await.unwrap().unwrap();
+    assert_eq!(book.last_update_id(), 163);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn persistent_gaps_back_off() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let snapshot = {
+        let calls = calls.clone();
+        move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async { Ok(snapshot_160()) }
+        }
+    };
+    // 170.. never follows the snapshot at 160
+    let events = stream::iter(diffs().into_iter().skip(3)).chain(stream::pending());
+    let s = synced_book_stream(events, snapshot, fast_policy());
+    pin_mut!(s);
+    assert!(tokio::time::timeout(Duration::from_millis(200), s.next()).await.is_err());
+    // snapshots at 0, 20, 50, 95 and 162ms rather than one per diff
+    let calls = calls.load(Ordering::SeqCst);
+    assert!((2..=5).contains(&calls), "{} snapshots", calls);
+}
+
+#[tokio::test]
+async fn permanent_snapshot_errors_end_the_stream() {
+    let snapshot = || async { Err(Error::ApiError(vec!["Invalid symbol.".into()]))? };
+    let events = stream::iter(diffs()).chain(stream::pending());
+    let s = synced_book_stream(events, snapshot, fast_policy());
+    pin_mut!(s);
+    let err = s.next().await.unwrap().unwrap_err();
+    assert_eq!(err.to_string(), r#"API Error ["Invalid symbol."]"#);
+    assert!(s.next().await.is_none());
+}
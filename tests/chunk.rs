@@ -0,0 +1,121 @@
+mod common;
+
+use common::{temp_path, trade};
+use futures::stream::{self, TryStreamExt};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tickstream::binance_ws::{AggregateTrade, BookTicker};
+use tickstream::chunk_enum;
+use tickstream::streams::chunk::{AnyChunk, AnyDatum, Chunk};
+use tickstream::streams::file::{FileSink, FileSource};
+use tickstream::streams::{Chunk2, StreamDatum};
+use tickstream::{BookUpdate, Trade};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+struct Heartbeat {
+    time: u64,
+}
+
+impl StreamDatum for Heartbeat {
+    const ID: u16 = 900;
+}
+
+chunk_enum! {
+    enum TestChunk(TestDatum) {
+        Heartbeat(Heartbeat),
+        Trade(Trade),
+    }
+}
+
+fn ticker(i: u64) -> BookTicker {
+    BookTicker {
+        update_id: i,
+        symbol: "BNBUSDT".into(),
+        best_bid: Decimal::new(2500 + i as i64, 1),
+        bist_bid_qty: 1.into(),
+        best_ask: Decimal::new(2510 + i as i64, 1),
+        best_ask_qty: 2.into(),
+    }
+}
+
+fn agg_trade(i: u64) -> AggregateTrade {
+    AggregateTrade {
+        event: "aggTrade".into(),
+        event_time: i,
+        symbol: "BNBUSDT".into(),
+        trade_id: i,
+        price: Decimal::new(2505, 1),
+        quantity: 3.into(),
+        first_trade: i,
+        last_trade: i + 2,
+        trade_time: i,
+        maker: false,
+        _ignore: Some(true),
+    }
+}
+
+#[test]
+fn chunk_enum_batches_by_type() {
+    let mut chunk = TestChunk::new(TestDatum::Heartbeat(Heartbeat { time: 1 }));
+    assert_eq!(chunk.push(TestDatum::Heartbeat(Heartbeat { time: 2 })), None);
+    assert_eq!(chunk.push(TestDatum::Trade(trade("BNBUSDT", 3))), Some(TestDatum::Trade(trade("BNBUSDT", 3))));
+    assert_eq!(chunk.id(), 900);
+    assert_eq!(chunk.len(), 2);
+    assert_eq!(
+        TestChunk::types().iter().map(|t| t.id).collect::<Vec<_>>(),
+        vec![900, Trade::ID]
+    );
+
+    let bytes = bincode::serialize(&chunk).unwrap();
+    let decoded: TestChunk = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(
+        decoded.into_items(),
+        vec![
+            TestDatum::Heartbeat(Heartbeat { time: 1 }),
+            TestDatum::Heartbeat(Heartbeat { time: 2 })
+        ]
+    );
+}
+
+#[test]
+fn unknown_ids_are_reported() {
+    let bytes = bincode::serialize(&AnyChunk::BookTicker(vec![ticker(1)])).unwrap();
+
+    let err = bincode::deserialize::<TestChunk>(&bytes).unwrap_err().to_string();
+    assert!(err.contains("unknown StreamDatum ID 100"), "{}", err);
+    assert!(err.contains("900 (") && err.contains("Heartbeat"), "{}", err);
+
+    let err = bincode::deserialize::<Chunk2<BookUpdate, Trade>>(&bytes)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("unknown StreamDatum ID 100"), "{}", err);
+    assert!(err.contains("501 (") && err.contains("500 ("), "{}", err);
+}
+
+#[tokio::test]
+async fn file_holds_any_number_of_types() {
+    let path = temp_path("any");
+    let items = vec![
+        AnyDatum::BookTicker(ticker(1)),
+        AnyDatum::BookTicker(ticker(2)),
+        AnyDatum::AggregateTrade(agg_trade(3)),
+        AnyDatum::Trade(trade("BNBUSDT", 4)),
+        AnyDatum::BookTicker(ticker(5)),
+    ];
+
+    FileSink::<AnyChunk>::create(&path, "test", 10)
+        .await
+        .unwrap()
+        .record(stream::iter(items.clone().into_iter().map(Ok)))
+        .await
+        .unwrap();
+
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    let ids: Vec<u16> = source.chunks().map_ok(|c| c.id()).try_collect().await.unwrap();
+    assert_eq!(ids, vec![100, 104, 500, 100]);
+
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    let replayed: Vec<AnyDatum> = source.into_stream().try_collect().await.unwrap();
+    assert_eq!(replayed, items);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{temp_path, trade};
+use bytes::BytesMut;
+use futures::stream::{StreamExt, TryStreamExt};
+use tickstream::streams::chunk::Chunk;
+use tickstream::streams::codec::{read_chunks, ChunkCodec};
+use tickstream::streams::file::{FileSink, FileSource};
+use tickstream::streams::{Chunk2, Item2};
+use tickstream::types::Error;
+use tickstream::{BookUpdate, Trade};
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::{Decoder, Encoder};
+
+type TestChunk = Chunk2<BookUpdate, Trade>;
+
+fn trades(from: u64, n: u64) -> TestChunk {
+    Chunk2::B(
+        (from..from + n)
+            .map(|i| trade("BTCUSD", i))
+            .collect(),
+    )
+}
+
+fn encode(chunks: &[TestChunk]) -> Vec<u8> {
+    let mut codec = ChunkCodec::new();
+    let mut buf = BytesMut::new();
+    for chunk in chunks {
+        codec.encode(chunk.clone(), &mut buf).unwrap();
+    }
+    assert_eq!(codec.position(), buf.len() as u64);
+    buf.to_vec()
+}
+
+#[tokio::test]
+async fn decodes_chunk_by_chunk_from_any_reader() {
+    let chunks: Vec<TestChunk> = (0..20).map(|i| trades(i * 10, 10)).collect();
+    let bytes = encode(&chunks);
+
+    // a pipe far smaller than a single chunk
+    let (mut wr, rd) = tokio::io::duplex(16);
+    let writer = tokio::spawn(async move {
+        wr.write_all(&bytes).await.unwrap();
+    });
+    let decoded: Vec<TestChunk> = read_chunks(rd).try_collect().await.unwrap();
+    writer.await.unwrap();
+    assert_eq!(decoded, chunks);
+}
+
+#[tokio::test]
+async fn truncated_final_frame_reports_where_to_resume() {
+    let chunks = vec![trades(0, 3), trades(3, 3)];
+    let bytes = encode(&chunks);
+    let first_len = encode(&chunks[..1]).len();
+
+    let decoded: Vec<_> = read_chunks::<TestChunk, _>(&bytes[..bytes.len() - 5]).collect().await;
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[0].as_ref().unwrap(), &chunks[0]);
+    let err = decoded[1].as_ref().unwrap_err();
+    match err.downcast_ref::<Error>() {
+        Some(Error::TruncatedChunk { offset }) => assert_eq!(*offset, first_len as u64),
+        _ => panic!("unexpected error {:?}", err),
+    }
+
+    // a cut inside the length prefix too
+    let decoded: Vec<_> = read_chunks::<TestChunk, _>(&bytes[..first_len + 2]).collect().await;
+    assert!(decoded[1].is_err());
+}
+
+#[test]
+fn oversized_frames_are_refused() {
+    let bytes = encode(&[trades(0, 100)]);
+    let mut codec = ChunkCodec::<TestChunk>::with_max_len(64);
+    let err = codec.decode(&mut BytesMut::from(&bytes[..])).unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::ChunkTooLong { max: 64, .. })));
+    let err = codec.encode(trades(0, 100), &mut BytesMut::new()).unwrap_err();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::ChunkTooLong { max: 64, .. })));
+}
+
+#[tokio::test]
+async fn sink_resumes_after_a_truncated_frame() {
+    let path = temp_path("resume");
+    let mut sink = FileSink::<TestChunk>::create(&path, "test", 2).await.unwrap();
+    for item in trades(0, 4).into_items() {
+        sink.send(item).await.unwrap();
+    }
+    drop(sink);
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+    let mut sink = FileSink::<TestChunk>::create(&path, "test", 2).await.unwrap();
+    for item in trades(10, 2).into_items() {
+        sink.send(item).await.unwrap();
+    }
+    drop(sink);
+
+    let items: Vec<Item2<BookUpdate, Trade>> = FileSource::<TestChunk>::open(&path)
+        .await
+        .unwrap()
+        .into_stream()
+        .try_collect()
+        .await
+        .unwrap();
+    let times: Vec<u64> = items
+        .iter()
+        .map(|i| match i {
+            Item2::B(t) => t.event_time,
+            Item2::A(b) => b.event_time,
+        })
+        .collect();
+    assert_eq!(times, vec![0, 1, 10, 11]);
+    std::fs::remove_file(&path).unwrap();
+}
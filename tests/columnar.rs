@@ -0,0 +1,197 @@
+mod common;
+
+use common::temp_path;
+use bytes::BytesMut;
+use futures::TryStreamExt;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use tickstream::streams::chunk::{AnyChunk, AnyDatum};
+use tickstream::streams::codec::ChunkCodec;
+use tickstream::streams::columnar;
+use tickstream::streams::file::{FileSink, FileSource};
+use tickstream::streams::format::Encoding;
+use tickstream::vendor::binance_ws::{AggregateTrade, BookDepthUpdate, BookTicker};
+use tickstream::{BookUpdate, Trade};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn decimal() -> impl Strategy<Value = Decimal> {
+    (any::<i64>(), 0..=12u32).prop_map(|(mantissa, scale)| Decimal::new(mantissa, scale))
+}
+
+fn levels() -> impl Strategy<Value = Vec<(Decimal, Decimal)>> {
+    vec((decimal(), decimal()), 0..20)
+}
+
+fn depth_update() -> impl Strategy<Value = BookDepthUpdate> {
+    (any::<u64>(), "[A-Z]{3,8}", any::<u64>(), any::<u64>(), levels(), levels()).prop_map(
+        |(event_time, symbol, first_update_id, last_update_id, bids, asks)| BookDepthUpdate {
+            event: "depthUpdate".into(),
+            event_time,
+            symbol,
+            first_update_id,
+            last_update_id,
+            bids,
+            asks,
+        },
+    )
+}
+
+fn book_update() -> impl Strategy<Value = BookUpdate> {
+    depth_update().prop_map(|u| BookUpdate {
+        event: u.event,
+        event_time: u.event_time,
+        symbol: u.symbol,
+        first_update_id: u.first_update_id,
+        last_update_id: u.last_update_id,
+        bids: u.bids,
+        asks: u.asks,
+    })
+}
+
+fn trade() -> impl Strategy<Value = Trade> {
+    (
+        any::<u64>(),
+        ".{0,8}",
+        decimal(),
+        decimal(),
+        any::<Option<u64>>(),
+        any::<Option<u64>>(),
+        any::<u64>(),
+        any::<bool>(),
+    )
+        .prop_map(
+            |(event_time, symbol, price, quantity, buyer, seller, trade_time, maker)| Trade {
+                event: "trade".into(),
+                event_time,
+                symbol,
+                price,
+                quantity,
+                buyer,
+                seller,
+                trade_time,
+                maker,
+            },
+        )
+}
+
+fn aggregate_trade() -> impl Strategy<Value = AggregateTrade> {
+    (trade(), any::<u64>(), any::<Option<bool>>()).prop_map(|(t, trade_id, ignore)| AggregateTrade {
+        event: "aggTrade".into(),
+        event_time: t.event_time,
+        symbol: t.symbol,
+        trade_id,
+        price: t.price,
+        quantity: t.quantity,
+        first_trade: t.trade_time,
+        last_trade: t.event_time,
+        trade_time: t.trade_time,
+        maker: t.maker,
+        _ignore: ignore,
+    })
+}
+
+fn book_ticker() -> impl Strategy<Value = BookTicker> {
+    (any::<u64>(), "[A-Z]{3,8}", decimal(), decimal(), decimal(), decimal()).prop_map(
+        |(update_id, symbol, best_bid, bist_bid_qty, best_ask, best_ask_qty)| BookTicker {
+            update_id,
+            symbol,
+            best_bid,
+            bist_bid_qty,
+            best_ask,
+            best_ask_qty,
+        },
+    )
+}
+
+fn chunk() -> impl Strategy<Value = AnyChunk> {
+    prop_oneof![
+        vec(depth_update(), 0..10).prop_map(AnyChunk::BookDepthUpdate),
+        vec(book_update(), 0..10).prop_map(AnyChunk::BookUpdate),
+        vec(trade(), 0..50).prop_map(AnyChunk::Trade),
+        vec(aggregate_trade(), 0..50).prop_map(AnyChunk::AggregateTrade),
+        vec(book_ticker(), 0..50).prop_map(AnyChunk::BookTicker),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn chunks_round_trip(chunk in chunk()) {
+        let bytes = columnar::encode(&chunk).unwrap();
+        prop_assert_eq!(columnar::decode::<AnyChunk>(&bytes).unwrap(), chunk.clone());
+
+        let mut codec = ChunkCodec::with_encoding(Encoding::Columnar);
+        let mut buf = BytesMut::new();
+        codec.encode(chunk.clone(), &mut buf).unwrap();
+        prop_assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(chunk));
+        prop_assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn any_strings_round_trip(values in vec((".*", any::<Option<i32>>(), any::<f64>()), 0..20)) {
+        let bytes = columnar::encode(&values).unwrap();
+        let decoded: Vec<(String, Option<i32>, f64)> = columnar::decode(&bytes).unwrap();
+        prop_assert_eq!(decoded.len(), values.len());
+        for (d, v) in decoded.iter().zip(&values) {
+            prop_assert_eq!(&d.0, &v.0);
+            prop_assert_eq!(d.1, v.1);
+            prop_assert_eq!(d.2.to_bits(), v.2.to_bits());
+        }
+    }
+}
+
+/// A minute of a busy depth stream, prices a few ticks apart
+fn recorded_updates() -> Vec<BookUpdate> {
+    (0..1000u64)
+        .map(|i| BookUpdate {
+            event: "depthUpdate".into(),
+            event_time: 1_616_000_000_000 + i * 100,
+            symbol: "BTCUSDT".into(),
+            first_update_id: 9_000_000 + i * 7,
+            last_update_id: 9_000_000 + i * 7 + 6,
+            bids: (0..5)
+                .map(|j| (Decimal::new(5_800_000 - (i as i64 % 13) - j, 2), Decimal::new(1000 + i as i64 * j, 6)))
+                .collect(),
+            asks: (0..5)
+                .map(|j| (Decimal::new(5_800_100 + (i as i64 % 11) + j, 2), Decimal::new(2000 + i as i64, 6)))
+                .collect(),
+        })
+        .collect()
+}
+
+#[test]
+fn columnar_chunks_are_smaller() {
+    let chunk = AnyChunk::BookUpdate(recorded_updates());
+    let mut bincode = ChunkCodec::new();
+    let mut columnar = ChunkCodec::with_encoding(Encoding::Columnar);
+    let (mut a, mut b) = (BytesMut::new(), BytesMut::new());
+    bincode.encode(chunk.clone(), &mut a).unwrap();
+    columnar.encode(chunk, &mut b).unwrap();
+    assert!(b.len() * 5 < a.len(), "{} vs {} bytes", b.len(), a.len());
+}
+
+#[tokio::test]
+async fn columnar_files_round_trip() {
+    let path = temp_path("columnar");
+    let updates = recorded_updates();
+
+    let mut sink = FileSink::<AnyChunk>::create_with_encoding(&path, "test", 100, Encoding::Columnar)
+        .await
+        .unwrap();
+    for update in &updates {
+        sink.send(AnyDatum::BookUpdate(update.clone())).await.unwrap();
+    }
+    sink.flush().await.unwrap();
+    // reopening keeps the file's encoding
+    let mut sink = FileSink::<AnyChunk>::create(&path, "test", 100).await.unwrap();
+    sink.send(AnyDatum::BookUpdate(updates[0].clone())).await.unwrap();
+    sink.flush().await.unwrap();
+
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    assert_eq!(source.header().encoding, Encoding::Columnar);
+    let items: Vec<AnyDatum> = source.into_stream().try_collect().await.unwrap();
+    let mut expected: Vec<AnyDatum> = updates.into_iter().map(AnyDatum::BookUpdate).collect();
+    expected.push(expected[0].clone());
+    assert_eq!(items, expected);
+    std::fs::remove_file(&path).unwrap();
+}
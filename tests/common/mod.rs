@@ -0,0 +1,222 @@
+#![allow(dead_code, clippy::result_large_err)]
+use futures::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tickstream::streams::index::index_path;
+use tickstream::Trade;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+pub fn fixture(name: &str) -> String {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect();
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+pub fn fixture_lines(name: &str) -> Vec<String> {
+    fixture(name)
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub fn dec(s: &str) -> Decimal {
+    Decimal::from_str(s).unwrap()
+}
+
+/// A path in the temp dir for a recording, `name` unique across the tests.
+/// Any recording and index left there by an earlier run are removed.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tickstream-{}-{}.bin", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(index_path(&path));
+    path
+}
+
+/// Remove a recording made at `temp_path` and its index
+pub fn remove(path: &std::path::Path) {
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(index_path(path)).unwrap();
+}
+
+/// A Binance trade of `symbol` at `time`, for the event and trade time
+pub fn trade(symbol: &str, time: u64) -> Trade {
+    Trade {
+        event: "trade".into(),
+        event_time: time,
+        symbol: symbol.into(),
+        price: Decimal::new(1005, 1),
+        quantity: 1.into(),
+        buyer: Some(1),
+        seller: Some(2),
+        trade_time: time,
+        maker: false,
+    }
+}
+
+/// What the mock server saw from the client
+#[derive(Debug)]
+pub struct Seen {
+    pub uri: String,
+    pub messages: Vec<String>,
+}
+
+/// Accept a single websocket client, read `expect_msgs` text messages from
+/// it, answer with `replies` and then replay `frames`. The connection is
+/// held open until the client goes away.
+pub async fn mock_ws_server(
+    expect_msgs: usize,
+    replies: Vec<String>,
+    frames: Vec<String>,
+) -> (String, oneshot::Receiver<Seen>) {
+    let (url, mut sessions) = mock_ws_sessions(expect_msgs, vec![replies.into_iter().chain(frames).collect()]).await;
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Some(seen) = sessions.recv().await {
+            let _ = tx.send(seen);
+        }
+    });
+    (url, rx)
+}
+
+/// Accept the next websocket client on `listener`, returning the uri it
+/// asked for
+pub async fn accept_ws(listener: &TcpListener) -> (String, WebSocketStream<TcpStream>) {
+    let (tcp, _) = listener.accept().await.unwrap();
+    let mut uri = String::new();
+    let ws = tokio_tungstenite::accept_hdr_async(tcp, |req: &Request, resp: Response| {
+        uri = req.uri().to_string();
+        Ok(resp)
+    })
+    .await
+    .unwrap();
+    (uri, ws)
+}
+
+/// Like `mock_ws_server` but serving one connection per entry of
+/// `sessions` in turn. All but the last connection are dropped without a
+/// close handshake once their frames have been sent.
+pub async fn mock_ws_sessions<M: Into<Message> + Send + 'static>(
+    expect_msgs: usize,
+    sessions: Vec<Vec<M>>,
+) -> (String, mpsc::UnboundedReceiver<Seen>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let last = sessions.len() - 1;
+        for (n, frames) in sessions.into_iter().enumerate() {
+            let (uri, ws) = accept_ws(&listener).await;
+            let (mut wr, mut rd) = ws.split();
+            let mut messages = vec![];
+            while messages.len() < expect_msgs {
+                match rd.next().await {
+                    Some(Ok(Message::Text(t))) => messages.push(t),
+                    Some(Ok(_)) => continue,
+                    _ => return,
+                }
+            }
+            let _ = tx.send(Seen { uri, messages });
+            for f in frames {
+                if wr.send(f.into()).await.is_err() {
+                    return;
+                }
+            }
+            if n == last {
+                while let Some(Ok(_)) = rd.next().await {}
+            }
+        }
+    });
+    (format!("ws://{}", addr), rx)
+}
+
+/// A response of `mock_http_server`, a plain body is served with 200 OK
+pub struct MockResponse {
+    pub status: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl From<String> for MockResponse {
+    fn from(body: String) -> Self {
+        MockResponse {
+            status: "200 OK",
+            headers: vec![],
+            body,
+        }
+    }
+}
+
+/// Serve `routes` (path → JSON body) over plain HTTP/1.1 and record the
+/// request target (path and query) of every request made. A path that is
+/// listed more than once serves its bodies in turn, repeating the last.
+pub async fn mock_http_server<R: Into<MockResponse>>(
+    routes: Vec<(&'static str, R)>,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    let routes: Vec<(&'static str, MockResponse)> = routes.into_iter().map(|(p, r)| (p, r.into())).collect();
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let seen = Arc::new(Mutex::new(Vec::<String>::new()));
+    let routes = Arc::new(routes);
+    let seen_srv = seen.clone();
+    tokio::spawn(async move {
+        while let Ok((mut tcp, _)) = listener.accept().await {
+            let routes = routes.clone();
+            let seen = seen_srv.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![];
+                let mut tmp = [0u8; 4096];
+                loop {
+                    let end = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        Some(pos) => pos + 4,
+                        None => match tcp.read(&mut tmp).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => {
+                                buf.extend_from_slice(&tmp[..n]);
+                                continue;
+                            }
+                        },
+                    };
+                    let head = String::from_utf8_lossy(&buf[..end]).to_string();
+                    buf.drain(..end);
+                    let target = head.split_whitespace().nth(1).unwrap_or("").to_string();
+                    let path = target.split('?').next().unwrap_or("").to_string();
+                    let served = {
+                        let mut seen = seen.lock().unwrap();
+                        let served = seen.iter().filter(|t| t.split('?').next() == Some(path.as_str())).count();
+                        seen.push(target.clone());
+                        served
+                    };
+                    let bodies: Vec<&MockResponse> = routes.iter().filter(|(p, _)| *p == path).map(|(_, r)| r).collect();
+                    let not_found = MockResponse {
+                        status: "404 Not Found",
+                        headers: vec![],
+                        body: String::new(),
+                    };
+                    let r = bodies.get(served.min(bodies.len().max(1) - 1)).copied().unwrap_or(&not_found);
+                    let headers: String = r.headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+                    let resp = format!(
+                        "HTTP/1.1 {}\r\ncontent-type: application/json\r\n{}content-length: {}\r\n\r\n{}",
+                        r.status,
+                        headers,
+                        r.body.len(),
+                        r.body
+                    );
+                    if tcp.write_all(resp.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    (format!("http://{}", addr), seen)
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{temp_path, trade};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use rust_decimal::Decimal;
+use tickstream::streams::file::{MixedFileSink2, MixedFileSource2};
+use tickstream::streams::{Chunk2, Item2};
+use tickstream::{BookUpdate, Trade};
+
+fn book(i: u64) -> BookUpdate {
+    BookUpdate {
+        event: "depthUpdate".into(),
+        event_time: i,
+        symbol: "BTCUSD".into(),
+        first_update_id: i,
+        last_update_id: i,
+        bids: vec![(Decimal::new(i as i64 * 100 + 1, 2), 1.into())],
+        asks: vec![(Decimal::new(i as i64 * 100 + 2, 2), 2.into())],
+    }
+}
+
+#[tokio::test]
+async fn replays_items_in_recorded_order() {
+    let path = temp_path("order");
+    let items = vec![
+        Item2::A(book(1)),
+        Item2::A(book(2)),
+        Item2::A(book(3)),
+        Item2::B(trade("BTCUSD", 4)),
+        Item2::A(book(5)),
+        Item2::B(trade("BTCUSD", 6)),
+        Item2::B(trade("BTCUSD", 7)),
+    ];
+
+    let mut sink = MixedFileSink2::<BookUpdate, Trade>::create(&path, "test", 2).await.unwrap();
+    for item in items.clone() {
+        match item {
+            Item2::A(b) => sink.send_a(b).await.unwrap(),
+            Item2::B(t) => sink.send_b(t).await.unwrap(),
+        }
+    }
+    sink.flush().await.unwrap();
+
+    let chunks: Vec<Chunk2<BookUpdate, Trade>> = MixedFileSource2::open(&path)
+        .await
+        .unwrap()
+        .chunks()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(chunks.iter().map(Chunk2::len).collect::<Vec<_>>(), vec![2, 1, 1, 1, 2]);
+
+    let replayed: Vec<Item2<BookUpdate, Trade>> = MixedFileSource2::open(&path)
+        .await
+        .unwrap()
+        .into_stream()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(replayed, items);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn records_two_streams_and_appends() {
+    let path = temp_path("record");
+    let books = stream::iter((0..10).map(|i| Ok(book(i))));
+    let trades = stream::iter((0..5).map(|i| Ok(trade("BTCUSD", i))));
+    MixedFileSink2::<BookUpdate, Trade>::create(&path, "test", 100)
+        .await
+        .unwrap()
+        .record(books, trades)
+        .await
+        .unwrap();
+
+    // a failing stream still gets what came before it written out
+    let books = stream::iter(vec![Ok(book(10)), Err(anyhow::anyhow!("lost"))]);
+    let err = MixedFileSink2::<BookUpdate, Trade>::create(&path, "test", 100)
+        .await
+        .unwrap()
+        .record(books, stream::empty())
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "lost");
+
+    let replayed: Vec<Item2<BookUpdate, Trade>> = MixedFileSource2::open(&path)
+        .await
+        .unwrap()
+        .into_stream()
+        .try_collect()
+        .await
+        .unwrap();
+    let mut books = vec![];
+    let mut trades = vec![];
+    for item in replayed {
+        match item {
+            Item2::A(b) => books.push(b),
+            Item2::B(t) => trades.push(t),
+        }
+    }
+    assert_eq!(books, (0..=10).map(book).collect::<Vec<_>>());
+    assert_eq!(trades, (0..5).map(|i| trade("BTCUSD", i)).collect::<Vec<_>>());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn truncated_file_is_an_error() {
+    let path = temp_path("truncated");
+    let mut sink = MixedFileSink2::<BookUpdate, Trade>::create(&path, "test", 10).await.unwrap();
+    sink.send_a(book(1)).await.unwrap();
+    sink.send_b(trade("BTCUSD", 2)).await.unwrap();
+    sink.flush().await.unwrap();
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+    let items: Vec<_> = MixedFileSource2::<BookUpdate, Trade>::open(&path)
+        .await
+        .unwrap()
+        .into_stream()
+        .collect()
+        .await;
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].as_ref().unwrap(), &Item2::A(book(1)));
+    assert!(items[1].is_err());
+    std::fs::remove_file(&path).unwrap();
+}
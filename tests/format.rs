@@ -0,0 +1,242 @@
+mod common;
+
+use common::temp_path;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tickstream::chunk_enum;
+use tickstream::streams::chunk::{Chunk, DatumType};
+use tickstream::streams::file::{FileSink, FileSource};
+use tickstream::streams::format::{Encoding, FileHeader, MAGIC, VERSION};
+use tickstream::streams::schema::{schema, schema_hash};
+use tickstream::streams::StreamDatum;
+use tickstream::types::Error;
+use tickstream::{BookUpdate, Trade};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+struct Heartbeat {
+    time: u64,
+}
+
+impl StreamDatum for Heartbeat {
+    const ID: u16 = 900;
+}
+
+/// `Heartbeat` after a field was added
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+struct HeartbeatV2 {
+    time: u64,
+    seq: u64,
+}
+
+impl StreamDatum for HeartbeatV2 {
+    const ID: u16 = 900;
+}
+
+chunk_enum! {
+    enum V1Chunk(V1) {
+        Heartbeat(Heartbeat),
+        Trade(Trade),
+    }
+}
+
+chunk_enum! {
+    enum V2Chunk(V2) {
+        Heartbeat(HeartbeatV2),
+        Trade(Trade),
+    }
+}
+
+#[test]
+fn schemas_describe_the_bincode_layout() {
+    assert_eq!(schema::<Trade>(), "{ str u64 str str str option< u64 > option< u64 > u64 bool }");
+    assert_eq!(
+        schema::<BookUpdate>(),
+        "{ str u64 str u64 u64 seq< ( str str ) > seq< ( str str ) > }"
+    );
+    assert_eq!(schema::<Option<Vec<(u8, String)>>>(), "option< seq< ( u8 str ) > >");
+    assert_eq!(schema_hash::<Trade>(), 0xac93_4baf_d848_3d61);
+    assert_ne!(schema_hash::<Heartbeat>(), schema_hash::<HeartbeatV2>());
+}
+
+#[allow(dead_code)]
+mod v1 {
+    #[derive(serde::Deserialize)]
+    pub enum Status {
+        Open,
+        Closed { time: u64 },
+        Halted(Option<super::v1::Reason>),
+    }
+
+    #[derive(serde::Deserialize)]
+    pub enum Reason {
+        Auction,
+        Maintenance(u64),
+    }
+}
+
+/// `v1` after a variant other than the first one changed
+#[allow(dead_code)]
+mod v2 {
+    #[derive(serde::Deserialize)]
+    pub enum Status {
+        Open,
+        Closed { time: u64, price: String },
+        Halted(Option<super::v1::Reason>),
+    }
+
+    #[derive(serde::Deserialize)]
+    pub enum Reason {
+        Auction,
+        Upgrade(u64),
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+enum List {
+    Nil,
+    Cons(u8, Box<List>),
+}
+
+#[test]
+fn schemas_describe_every_enum_variant() {
+    assert_eq!(
+        schema::<v1::Status>(),
+        "enum3< Open: unit | Closed: { u64 } | Halted: option< enum2< Auction: unit | Maintenance: u64 > > >"
+    );
+    assert_ne!(schema_hash::<v1::Status>(), schema_hash::<v2::Status>());
+    assert_ne!(schema_hash::<v1::Reason>(), schema_hash::<v2::Reason>());
+    assert_eq!(schema::<List>(), "enum2< Nil: unit | Cons: ( u8 recursive >");
+}
+
+#[test]
+fn header_byte_layout() {
+    let header = FileHeader {
+        version: VERSION,
+        created: 0x0102_0304_0506_0708,
+        vendor: "bn".into(),
+        types: vec![DatumType {
+            id: 500,
+            name: "T".into(),
+            schema: 0x1122_3344_5566_7788,
+        }],
+        encoding: Encoding::Columnar,
+    };
+    #[rustfmt::skip]
+    let expected: Vec<u8> = vec![
+        b'T', b'I', b'C', b'K',
+        2, 0,
+        49, 0, 0, 0,
+        8, 7, 6, 5, 4, 3, 2, 1,
+        2, 0, 0, 0, 0, 0, 0, 0, b'b', b'n',
+        1, 0, 0, 0, 0, 0, 0, 0,
+        0xf4, 0x01,
+        1, 0, 0, 0, 0, 0, 0, 0, b'T',
+        0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11,
+        1, 0, 0, 0,
+    ];
+    assert_eq!(header.encode().unwrap(), expected);
+}
+
+#[tokio::test]
+async fn version_1_files_are_read_as_bincode() {
+    let path = temp_path("format-v1");
+    let v1 = FileHeader {
+        version: 1,
+        encoding: Encoding::Bincode,
+        ..FileHeader::new::<V1Chunk>("test", Encoding::Bincode)
+    };
+    let mut bytes = v1.encode().unwrap();
+    // version 1 bodies end with the types
+    bytes.truncate(bytes.len() - 4);
+    let body_len = (bytes.len() - 10) as u32;
+    bytes[6..10].copy_from_slice(&body_len.to_le_bytes());
+    let chunk = bincode::serialize(&V1Chunk::Heartbeat(vec![Heartbeat { time: 7 }])).unwrap();
+    bytes.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&chunk);
+    std::fs::write(&path, &bytes).unwrap();
+
+    let source = FileSource::<V1Chunk>::open(&path).await.unwrap();
+    assert_eq!(*source.header(), v1);
+    let items: Vec<V1> = source.into_stream().try_collect().await.unwrap();
+    assert_eq!(items, vec![V1::Heartbeat(Heartbeat { time: 7 })]);
+
+    // appending keeps writing bincode
+    let mut sink = FileSink::<V1Chunk>::create_with_encoding(&path, "test", 10, Encoding::Columnar)
+        .await
+        .unwrap();
+    sink.send(V1::Heartbeat(Heartbeat { time: 8 })).await.unwrap();
+    sink.flush().await.unwrap();
+    let items: Vec<V1> = FileSource::<V1Chunk>::open(&path)
+        .await
+        .unwrap()
+        .into_stream()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn chunk_frame_layout() {
+    let path = temp_path("format-layout");
+    let mut sink = FileSink::<V1Chunk>::create(&path, "test", 10).await.unwrap();
+    sink.send(V1::Heartbeat(Heartbeat { time: 7 })).await.unwrap();
+    sink.flush().await.unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(bytes[..4], MAGIC);
+    let header_len = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]) as usize;
+    #[rustfmt::skip]
+    let expected: Vec<u8> = vec![
+        0, 0, 0, 26,
+        2, 0, 0, 0, 0, 0, 0, 0,
+        0x84, 0x03,
+        1, 0, 0, 0, 0, 0, 0, 0,
+        7, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    assert_eq!(bytes[10 + header_len..], expected[..]);
+
+    let source = FileSource::<V1Chunk>::open(&path).await.unwrap();
+    assert_eq!(source.header().vendor, "test");
+    assert_eq!(source.header().types, V1Chunk::types());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn mismatched_files_are_refused() {
+    let path = temp_path("format-mismatch");
+    let mut sink = FileSink::<V1Chunk>::create(&path, "test", 10).await.unwrap();
+    sink.send(V1::Heartbeat(Heartbeat { time: 7 })).await.unwrap();
+    sink.flush().await.unwrap();
+
+    for err in [
+        FileSource::<V2Chunk>::open(&path).await.err().unwrap(),
+        FileSink::<V2Chunk>::create(&path, "test", 10).await.err().unwrap(),
+    ] {
+        match err.downcast_ref::<Error>() {
+            Some(Error::SchemaMismatch { id: 900, name }) => assert!(name.contains("HeartbeatV2")),
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    // types only one side knows don't matter
+    assert!(FileSource::<tickstream::streams::Chunk2<Trade, BookUpdate>>::open(&path).await.is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn foreign_files_are_refused() {
+    let path = temp_path("format-foreign");
+    std::fs::write(&path, b"not a tick file").unwrap();
+    let err = FileSource::<V1Chunk>::open(&path).await.err().unwrap();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::BadMagic)));
+
+    let mut future = FileHeader::new::<V1Chunk>("test", Encoding::Bincode).encode().unwrap();
+    future[4] = 99;
+    std::fs::write(&path, &future).unwrap();
+    let err = FileSource::<V1Chunk>::open(&path).await.err().unwrap();
+    assert!(matches!(err.downcast_ref::<Error>(), Some(Error::UnsupportedVersion(99))));
+    std::fs::remove_file(&path).unwrap();
+}
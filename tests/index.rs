@@ -0,0 +1,116 @@
+mod common;
+
+use common::{remove, temp_path, trade};
+use futures::TryStreamExt;
+use std::path::Path;
+use tickstream::streams::chunk::{AnyChunk, AnyDatum};
+use tickstream::streams::file::{FileSink, FileSource};
+use tickstream::streams::index::{index_path, TimeIndex};
+use tickstream::vendor::binance_ws::BookTicker;
+
+fn ticker() -> AnyDatum {
+    AnyDatum::BookTicker(BookTicker {
+        update_id: 1,
+        symbol: "BTCUSDT".into(),
+        best_bid: 1.into(),
+        bist_bid_qty: 1.into(),
+        best_ask: 2.into(),
+        best_ask_qty: 1.into(),
+    })
+}
+
+/// 1000 trades 10ms apart in chunks of 100, with a ticker after every 250th
+async fn record(path: &Path) {
+    let mut sink = FileSink::<AnyChunk>::create(path, "test", 100).await.unwrap();
+    for i in 0..1000 {
+        sink.send(AnyDatum::Trade(trade("BTCUSDT", i * 10))).await.unwrap();
+        if i % 250 == 249 {
+            sink.send(ticker()).await.unwrap();
+        }
+    }
+    sink.flush().await.unwrap();
+}
+
+fn times(items: &[AnyDatum]) -> Vec<u64> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            AnyDatum::Trade(t) => Some(t.event_time),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn seeks_to_the_chunk_covering_a_time() {
+    let path = temp_path("index-seek");
+    record(&path).await;
+
+    let index = TimeIndex::read(&path).await.unwrap().unwrap();
+    // tickers cut the trade chunks short, and aren't indexed
+    let ranges: Vec<(u64, u64)> = index.entries.iter().map(|e| (e.min_time, e.max_time)).collect();
+    assert_eq!(ranges.len(), 12);
+    assert_eq!(ranges[..4], [(0, 990), (1000, 1990), (2000, 2490), (2500, 3490)]);
+    assert_eq!(index, TimeIndex::build::<AnyChunk>(&path).await.unwrap());
+
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    assert_eq!(source.index(), Some(&index));
+    let items: Vec<AnyDatum> = source.seek_to(5005).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(times(&items), (501..1000).map(|i| i * 10).collect::<Vec<_>>());
+    // the tickers after trades 749 and 999 are kept
+    assert_eq!(items.len(), 499 + 2);
+
+    // past the end only the last ticker is left
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    let items: Vec<AnyDatum> = source.seek_to(100_000).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(items, vec![ticker()]);
+    remove(&path);
+}
+
+#[tokio::test]
+async fn seeking_without_an_index_reads_from_the_start() {
+    let path = temp_path("index-no-index");
+    record(&path).await;
+    std::fs::remove_file(index_path(&path)).unwrap();
+
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    assert!(source.index().is_none());
+    let items: Vec<AnyDatum> = source.seek_to(9000).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(times(&items), (900..1000).map(|i| i * 10).collect::<Vec<_>>());
+    // all tickers are kept, they have no time
+    assert_eq!(items.len(), 100 + 4);
+
+    // appending rebuilds it
+    let mut sink = FileSink::<AnyChunk>::create(&path, "test", 100).await.unwrap();
+    sink.send(AnyDatum::Trade(trade("BTCUSDT", 10_000))).await.unwrap();
+    sink.flush().await.unwrap();
+    let index = TimeIndex::read(&path).await.unwrap().unwrap();
+    assert_eq!(index.entries.len(), 13);
+    assert_eq!(index, TimeIndex::build::<AnyChunk>(&path).await.unwrap());
+    remove(&path);
+}
+
+#[tokio::test]
+async fn appending_catches_up_on_unindexed_chunks() {
+    let path = temp_path("index-catch-up");
+    record(&path).await;
+    let full = TimeIndex::read(&path).await.unwrap().unwrap();
+
+    // as if the writer died before indexing its last two chunks, the
+    // second one half written
+    let idx = std::fs::read(index_path(&path)).unwrap();
+    std::fs::write(index_path(&path), &idx[..idx.len() - 24 - 10]).unwrap();
+    let index = TimeIndex::read(&path).await.unwrap().unwrap();
+    assert_eq!(index.entries[..], full.entries[..10]);
+
+    FileSink::<AnyChunk>::create(&path, "test", 100).await.unwrap();
+    assert_eq!(TimeIndex::read(&path).await.unwrap().unwrap(), full);
+
+    // an index of another recording is ignored
+    let mut idx = std::fs::read(index_path(&path)).unwrap();
+    idx[4] ^= 1;
+    std::fs::write(index_path(&path), &idx).unwrap();
+    let source = FileSource::<AnyChunk>::open(&path).await.unwrap();
+    assert!(source.index().is_none());
+    remove(&path);
+}
@@ -0,0 +1,229 @@
+mod common;
+
+use common::{dec, fixture, mock_http_server};
+use tickstream::kraken_rest::{AssetPair, Candle, Interval, KrakenRestClient, Order, Scaled, Trade, TradeSide};
+use tickstream::types::Error;
+
+async fn client() -> (KrakenRestClient, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    let (url, seen) = mock_http_server(vec![
+        ("/0/public/Time", fixture("kraken/rest/time.json")),
+        ("/0/public/Assets", fixture("kraken/rest/assets.json")),
+        ("/0/public/AssetPairs", fixture("kraken/rest/asset_pairs.json")),
+        ("/0/public/Ticker", fixture("kraken/rest/ticker.json")),
+        ("/0/public/Depth", fixture("kraken/rest/depth.json")),
+        ("/0/public/OHLC", fixture("kraken/rest/ohlc.json")),
+        ("/0/public/Trades", fixture("kraken/rest/trades.json")),
+        ("/0/public/Spread", fixture("kraken/rest/spread.json")),
+        ("/0/public/Error", fixture("kraken/rest/error.json")),
+    ])
+    .await;
+    (KrakenRestClient::new(&url).unwrap(), seen)
+}
+
+#[tokio::test]
+async fn reference_data() {
+    let (client, seen) = client().await;
+
+    let time = client.server_time().await.unwrap();
+    assert_eq!(time.unixtime, 1616336594);
+
+    let assets = client.assets().await.unwrap();
+    assert_eq!(assets["XXBT"].altname, "XBT");
+    assert_eq!(assets["ZUSD"].decimals, 4);
+
+    let pairs = client.asset_pairs().await.unwrap();
+    let xbtusd = &pairs["XXBTZUSD"];
+    assert_eq!(xbtusd.wsname.as_deref(), Some("XBT/USD"));
+    assert_eq!(xbtusd.pair_decimals, 1);
+    assert_eq!(xbtusd.lot_decimals, 8);
+    assert_eq!(xbtusd.fees.len(), 3);
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["/0/public/Time", "/0/public/Assets", "/0/public/AssetPairs"]
+    );
+}
+
+#[tokio::test]
+async fn market_data() {
+    let (client, seen) = client().await;
+
+    let ticker = client.ticker(&["XBTUSD"]).await.unwrap();
+    assert_eq!(ticker["XXBTZUSD"].t, [10823, 29466]);
+
+    let depth = client.depth("XBTUSD", Some(2)).await.unwrap();
+    assert_eq!(depth["XXBTZUSD"].asks.len(), 2);
+    assert_eq!(depth["XXBTZUSD"].bids[0].timestamp, 1616336593);
+
+    let ohlc = client.ohlc("XBTUSD", Interval::M5, Some(1616336000)).await.unwrap();
+    assert_eq!(ohlc.last, 1616336520);
+    assert_eq!(ohlc.data["XXBTZUSD"][1].count, 9);
+
+    let trades = client.trades("XBTUSD", None).await.unwrap();
+    assert_eq!(trades.data["XXBTZUSD"].len(), 2);
+    assert_eq!(trades.last, 1616336595432100000);
+
+    let spread = client.spread("XBTUSD", Some(1616336594)).await.unwrap();
+    assert_eq!(spread.last, 1616336595);
+    assert_eq!(spread.data["XXBTZUSD"][0].time, 1616336594);
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "/0/public/Ticker?pair=XBTUSD",
+            // once, to check the scales of all market data
+            "/0/public/AssetPairs",
+            "/0/public/Depth?pair=XBTUSD&count=2",
+            "/0/public/OHLC?pair=XBTUSD&interval=5&since=1616336000",
+            "/0/public/Trades?pair=XBTUSD",
+            "/0/public/Spread?pair=XBTUSD&since=1616336594",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn prices_are_exact_and_within_the_pair_scale() {
+    let (client, _) = client().await;
+    let pairs = client.asset_pairs().await.unwrap();
+    let xbtusd: &AssetPair = &pairs["XXBTZUSD"];
+
+    let ticker = client.ticker(&["XBTUSD"]).await.unwrap();
+    let ticker = &ticker["XXBTZUSD"];
+    assert_eq!(ticker.a.price, dec("57176.4"));
+    assert_eq!(ticker.c.volume, dec("0.0011501"));
+    assert_eq!(ticker.v.last24, dec("3426.42318931"));
+    ticker.check_scale(xbtusd).unwrap();
+
+    let depth = client.depth("XBTUSD", None).await.unwrap();
+    assert_eq!(depth["XXBTZUSD"].bids[0].price - depth["XXBTZUSD"].asks[0].price, dec("-0.1"));
+    depth["XXBTZUSD"].check_scale(xbtusd).unwrap();
+
+    let ohlc = client.ohlc("XBTUSD", Interval::M1, None).await.unwrap();
+    assert_eq!(ohlc.data["XXBTZUSD"][0].volume, dec("3.1541"));
+    ohlc.data["XXBTZUSD"].check_scale(xbtusd).unwrap();
+
+    let trades = client.trades("XBTUSD", None).await.unwrap();
+    trades.data["XXBTZUSD"].check_scale(xbtusd).unwrap();
+    client.spread("XBTUSD", None).await.unwrap().data["XXBTZUSD"].check_scale(xbtusd).unwrap();
+
+    let order = Order {
+        price: dec("57176.35"),
+        volume: dec("1"),
+        timestamp: 1616336594,
+    };
+    match order.check_scale(xbtusd) {
+        Err(Error::ExcessScale { value, decimals }) => assert_eq!((value, decimals), (dec("57176.35"), 1)),
+        other => panic!("expected a scale error, got {:?}", other),
+    }
+    assert!(xbtusd.check_volume(dec("0.123456789")).is_err());
+}
+
+#[tokio::test]
+async fn market_data_beyond_the_pair_scale_is_an_error() {
+    let (url, seen) = mock_http_server(vec![
+        ("/0/public/AssetPairs", fixture("kraken/rest/asset_pairs.json")),
+        (
+            "/0/public/Spread",
+            r#"{"error":[],"result":{"XXBTZUSD":[[1616336594,"57176.35","57176.4"]],"last":1616336594}}"#.to_string(),
+        ),
+        (
+            "/0/public/Trades",
+            r#"{"error":[],"result":{"XXBTZUSD":[["57176.3","0.123456789",1616336595.4321,"s","l",""]],"last":"1"}}"#
+                .to_string(),
+        ),
+    ])
+    .await;
+    let client = KrakenRestClient::new(&url).unwrap();
+
+    match client.spread("XBTUSD", None).await {
+        Err(Error::ExcessScale { value, decimals }) => assert_eq!((value, decimals), (dec("57176.35"), 1)),
+        other => panic!("expected a scale error, got {:?}", other),
+    }
+    match client.clone().trades("XBTUSD", None).await {
+        Err(Error::ExcessScale { value, decimals }) => assert_eq!((value, decimals), (dec("0.123456789"), 8)),
+        other => panic!("expected a scale error, got {:?}", other),
+    }
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "/0/public/Spread?pair=XBTUSD",
+            "/0/public/AssetPairs",
+            "/0/public/Trades?pair=XBTUSD",
+            // in case the pair's scales changed
+            "/0/public/AssetPairs",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn changed_pair_scales_are_fetched_again() {
+    let pairs = fixture("kraken/rest/asset_pairs.json");
+    let (url, seen) = mock_http_server(vec![
+        ("/0/public/AssetPairs", pairs.clone()),
+        ("/0/public/AssetPairs", pairs.replace(r#""pair_decimals":1"#, r#""pair_decimals":2"#)),
+        ("/0/public/Spread", fixture("kraken/rest/spread.json")),
+        (
+            "/0/public/Spread",
+            r#"{"error":[],"result":{"XXBTZUSD":[[1616336596,"57176.35","57176.4"]],"last":1616336596}}"#.to_string(),
+        ),
+    ])
+    .await;
+    let client = KrakenRestClient::new(&url).unwrap();
+
+    client.spread("XBTUSD", None).await.unwrap();
+    let spread = client.spread("XBTUSD", Some(1616336595)).await.unwrap();
+    assert_eq!(spread.data["XXBTZUSD"][0].bid, dec("57176.35"));
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "/0/public/Spread?pair=XBTUSD",
+            "/0/public/AssetPairs",
+            "/0/public/Spread?pair=XBTUSD&since=1616336595",
+            "/0/public/AssetPairs",
+        ]
+    );
+}
+
+#[test]
+fn payloads_are_arrays() {
+    let trades = fixture("kraken/rest/trades.json");
+    let trades: serde_json::Value = serde_json::from_str(&trades).unwrap();
+    // with the trade id Kraken appends nowadays
+    let trade: Trade = serde_json::from_value(trades["result"]["XXBTZUSD"][1].clone()).unwrap();
+    assert_eq!((trade.price, trade.volume), (dec("57176.3"), dec("0.02")));
+    assert_eq!(trade.time, dec("1616336595.4321"));
+    assert!(matches!(trade.side, TradeSide::Sell));
+
+    let candle: Candle =
+        serde_json::from_str(r#"[1616336520,"57150.0","57180.1","57140.0","57176.4","57160.2","3.15410000",27]"#).unwrap();
+    assert_eq!((candle.time, candle.high, candle.count), (1616336520, dec("57180.1"), 27));
+
+    let order: Order = serde_json::from_str(r#"[57176.4, "1.000", 1616336594]"#).unwrap();
+    assert_eq!((order.price, order.volume), (dec("57176.4"), dec("1")));
+
+    let err = serde_json::from_str::<Order>(r#"["57176.40000","1.000"]"#).unwrap_err();
+    assert!(err.to_string().contains("missing field `timestamp`"), "{}", err);
+    assert!(serde_json::from_str::<Order>(r#"{"price":"1","volume":"1","timestamp":1}"#).is_err());
+}
+
+#[tokio::test]
+async fn api_errors_are_typed() {
+    let (url, _) = mock_http_server(vec![("/0/public/Depth", fixture("kraken/rest/error.json"))]).await;
+    let client = KrakenRestClient::new(&url).unwrap();
+
+    match client.depth("XBTABC", None).await {
+        Err(Error::ApiError(errors)) => assert_eq!(errors, vec!["EQuery:Unknown asset pair"]),
+        other => panic!("expected an API error, got {:?}", other),
+    }
+    assert!(matches!(client.server_time().await, Err(Error::HttpError(_))));
+}
+
+#[tokio::test]
+async fn paths_join_under_the_base_path() {
+    let (url, seen) = mock_http_server(vec![("/kraken/0/public/Time", fixture("kraken/rest/time.json"))]).await;
+    for base in [format!("{}/kraken", url), format!("{}/kraken/", url)] {
+        let client = KrakenRestClient::new(&base).unwrap();
+        assert_eq!(client.server_time().await.unwrap().unixtime, 1616336594);
+    }
+    assert_eq!(*seen.lock().unwrap(), vec!["/kraken/0/public/Time"; 2]);
+}
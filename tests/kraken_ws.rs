@@ -0,0 +1,154 @@
+mod common;
+
+use common::{dec, fixture_lines, mock_ws_server};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tickstream::kraken_rest::{Subscription, SubscriptionName};
+use tickstream::kraken_ws::{parse_book, parse_trades, KrakenPlatform, KrakenProtocol};
+use tickstream::streams::websockets::SubscriptionProtocol;
+use tickstream::{BookUpdate, Platform, Trade};
+
+#[tokio::test]
+async fn book_stream_subscribes_and_skips_events() {
+    let (url, seen) = mock_ws_server(1, vec![], fixture_lines("kraken/book.jsonl")).await;
+
+    let s = KrakenPlatform::new(&url)
+        .start_book_stream(&["XBT/USD"])
+        .await
+        .unwrap();
+    let books: Vec<BookUpdate> = s.take(3).map(|b| b.unwrap()).collect().await;
+
+    let seen = seen.await.unwrap();
+    let sub: Value = serde_json::from_str(&seen.messages[0]).unwrap();
+    assert_eq!(
+        sub,
+        json!({"event": "subscribe", "pair": ["XBT/USD"], "subscription": {"name": "book", "depth": 10}})
+    );
+
+    assert_eq!(
+        books[0],
+        BookUpdate {
+            event: "snapshot".into(),
+            event_time: 1534614248765,
+            symbol: "XBT/USD".into(),
+            first_update_id: 0,
+            last_update_id: 0,
+            bids: vec![
+                (dec("5541.2"), dec("1.529")),
+                (dec("5539.9"), dec("0.3")),
+            ],
+            asks: vec![
+                (dec("5541.3"), dec("2.507")),
+                (dec("5541.8"), dec("0.33")),
+            ],
+        }
+    );
+    assert_eq!(books[1].event, "update");
+    assert_eq!(books[1].event_time, 1534614248456);
+    assert!(books[1].bids.is_empty());
+    assert_eq!(books[1].asks.len(), 2);
+    assert_eq!(books[2].asks, vec![(dec("5541.3"), dec("0"))]);
+    assert_eq!(books[2].bids, vec![(dec("5541.2"), dec("1.529"))]);
+}
+
+#[tokio::test]
+async fn trade_stream_flattens_batches() {
+    let (url, seen) = mock_ws_server(1, vec![], fixture_lines("kraken/trade.jsonl")).await;
+
+    let s = KrakenPlatform::new(&url)
+        .start_trade_stream(&["XBT/USD", "XBT/EUR"])
+        .await
+        .unwrap();
+    let trades: Vec<Trade> = s.take(2).map(|t| t.unwrap()).collect().await;
+
+    let seen = seen.await.unwrap();
+    let sub: Value = serde_json::from_str(&seen.messages[0]).unwrap();
+    assert_eq!(sub["pair"], json!(["XBT/USD", "XBT/EUR"]));
+    assert_eq!(sub["subscription"], json!({"name": "trade"}));
+
+    assert_eq!(
+        trades[0],
+        Trade {
+            event: "trade".into(),
+            event_time: 1534614057321,
+            symbol: "XBT/USD".into(),
+            price: dec("5541.2"),
+            quantity: dec("0.15850568"),
+            buyer: None,
+            seller: None,
+            trade_time: 1534614057321,
+            maker: true,
+        }
+    );
+    assert_eq!(trades[1].price, dec("6060"));
+    assert!(!trades[1].maker);
+}
+
+#[tokio::test]
+async fn ticker_and_candle_streams() {
+    let (url, seen) = mock_ws_server(1, vec![], fixture_lines("kraken/ticker.jsonl")).await;
+    let mut tickers = KrakenPlatform::new(&url).start_ticker_stream(&["XBT/USD"]).await.unwrap();
+    let ticker = tickers.next().await.unwrap().unwrap();
+    let sub: Value = serde_json::from_str(&seen.await.unwrap().messages[0]).unwrap();
+    assert_eq!(sub["subscription"], json!({"name": "ticker"}));
+    assert_eq!((ticker.bid, ticker.ask), (dec("5525.1"), dec("5525.4")));
+    assert_eq!(ticker.instrument.base, "BTC");
+
+    let (url, seen) = mock_ws_server(1, vec![], fixture_lines("kraken/ohlc.jsonl")).await;
+    let kraken = KrakenPlatform::new(&url);
+    assert!(kraken.start_candle_stream(&["XBT/USD"], Duration::from_secs(120)).await.is_err());
+    let mut candles = kraken
+        .start_candle_stream(&["XBT/USD"], Duration::from_secs(300))
+        .await
+        .unwrap();
+    let candle = candles.next().await.unwrap().unwrap();
+    let sub: Value = serde_json::from_str(&seen.await.unwrap().messages[0]).unwrap();
+    assert_eq!(sub["subscription"], json!({"name": "ohlc", "interval": 5}));
+    assert_eq!(candle.end - candle.start, 300_000_000_000);
+    assert!(candle.start < candle.time && candle.time < candle.end);
+    assert_eq!((candle.close, candle.trades), (dec("3586.6"), Some(2)));
+
+    // Kraken doesn't aggregate trades
+    let err = kraken.start_agg_trade_stream(&["XBT/USD"]).await.err().unwrap();
+    assert_eq!(err.to_string(), "kraken has no aggregate trade stream");
+}
+
+#[test]
+fn failed_subscription_is_an_error() {
+    let msg = json!({
+        "errorMessage": "Currency pair not supported XBT/ABC",
+        "event": "subscriptionStatus",
+        "pair": "XBT/ABC",
+        "status": "error",
+        "subscription": {"name": "book"}
+    });
+    let err = parse_book(msg).unwrap_err();
+    assert!(err.to_string().contains("Currency pair not supported"));
+    assert!(parse_trades(json!({"event": "heartbeat"})).unwrap().is_empty());
+}
+
+#[test]
+fn protocol_sends_one_message_per_subscription() {
+    let protocol = KrakenProtocol {
+        url: "wss://ws.kraken.com".into(),
+        subscriptions: vec![
+            Subscription::new(SubscriptionName::Trade),
+            Subscription::new(SubscriptionName::Spread),
+        ],
+    };
+    let pairs = vec!["XBT/USD".to_string()];
+    let msgs: Vec<Value> = protocol
+        .unsubscribe(&pairs)
+        .unwrap()
+        .iter()
+        .map(|m| serde_json::from_str(m).unwrap())
+        .collect();
+
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0]["event"], "unsubscribe");
+    assert_eq!(msgs[0]["pair"], json!(["XBT/USD"]));
+    assert_eq!(msgs[0]["subscription"]["name"], "trade");
+    assert_eq!(msgs[1]["subscription"]["name"], "spread");
+    assert_eq!(protocol.url(&pairs), "wss://ws.kraken.com");
+}
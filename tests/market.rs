@@ -0,0 +1,203 @@
+mod common;
+
+use common::{dec, fixture_lines, mock_ws_server};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use tickstream::binance_ws::{parse_event, BinancePlatform};
+use tickstream::kraken_ws::{parse_events, KrakenPlatform};
+use tickstream::market::{BookDelta, Candle, Instrument, MarketEvent, Side, Spread, Trade, Venue};
+use tickstream::streams::StreamDatum;
+use tickstream::Platform;
+
+#[test]
+fn instruments_use_common_asset_names() {
+    let btc = Instrument::parse(Venue::Kraken, "XBT/USD").unwrap();
+    assert_eq!((btc.base.as_str(), btc.quote.as_str()), ("BTC", "USD"));
+    assert_eq!(btc.symbol, "XBT/USD");
+    assert_eq!(btc.to_string(), "BTC/USD@kraken");
+
+    let bnb = Instrument::parse(Venue::Binance, "bnbusdt").unwrap();
+    assert_eq!(bnb, Instrument::new(Venue::Binance, "BNB", "USDT", "bnbusdt"));
+    assert_eq!(
+        Instrument::parse(Venue::Binance, "ETHBTC").unwrap().to_string(),
+        "ETH/BTC@binance"
+    );
+    // quoted in an asset that isn't common, kept whole
+    let zar = Instrument::parse(Venue::Binance, "USDTZAR").unwrap();
+    assert_eq!(zar, Instrument::unsplit(Venue::Binance, "USDTZAR"));
+    assert_eq!((zar.base.as_str(), zar.quote.as_str()), ("", ""));
+    assert_eq!(zar.to_string(), "USDTZAR@binance");
+    assert_eq!(Instrument::parse(Venue::Binance, "USDT").unwrap().base, "");
+    assert!(Instrument::parse(Venue::Kraken, "XBTUSD").is_err());
+}
+
+#[test]
+fn binance_messages_become_events() {
+    let lines = fixture_lines("binance/depth.jsonl");
+    let msg: Value = serde_json::from_str(&lines[0]).unwrap();
+    let event = parse_event(msg["stream"].as_str().unwrap(), msg["data"].clone()).unwrap();
+    assert_eq!(
+        event,
+        MarketEvent::BookDelta(BookDelta {
+            instrument: Instrument::new(Venue::Binance, "BNB", "BTC", "BNBBTC"),
+            time: 1614556800123000000,
+            first_update_id: Some(157),
+            last_update_id: Some(160),
+            bids: vec![(dec("0.0024"), dec("10"))],
+            asks: vec![(dec("0.0026"), dec("100"))],
+        })
+    );
+    assert_eq!(event.event_time(), Some(1614556800123));
+
+    let lines = fixture_lines("binance/trade.jsonl");
+    let sides: Vec<Side> = lines
+        .iter()
+        .map(|l| {
+            let msg: Value = serde_json::from_str(l).unwrap();
+            match parse_event("bnbbtc@trade", msg["data"].clone()).unwrap() {
+                MarketEvent::Trade(t) => t.side,
+                e => panic!("not a trade {:?}", e),
+            }
+        })
+        .collect();
+    // the buyer was the maker of the first, so the seller was the aggressor
+    assert_eq!(sides, vec![Side::Sell, Side::Buy]);
+
+    let ticker = json!({"u": 400900217, "s": "BNBUSDT", "b": "25.35190000", "B": "31.21000000", "a": "25.36520000", "A": "40.66000000"});
+    match parse_event("bnbusdt@bookTicker", ticker).unwrap() {
+        MarketEvent::Ticker(t) => {
+            assert_eq!((t.bid, t.ask), (dec("25.3519"), dec("25.3652")));
+            assert_eq!(t.instrument.quote, "USDT");
+        }
+        e => panic!("not a ticker {:?}", e),
+    }
+    assert!(parse_event("bnbbtc@forceOrder", json!({})).is_err());
+}
+
+#[test]
+fn kraken_messages_become_events() {
+    let book: Vec<Vec<MarketEvent>> = fixture_lines("kraken/book.jsonl")
+        .iter()
+        .map(|l| parse_events(serde_json::from_str(l).unwrap()).unwrap())
+        .filter(|events| !events.is_empty())
+        .collect();
+    match &book[0][..] {
+        [MarketEvent::BookSnapshot(s)] => {
+            assert_eq!(s.time, 1534614248765567000);
+            assert_eq!(s.bids[0], (dec("5541.2"), dec("1.529")));
+            assert_eq!(s.last_update_id, None);
+        }
+        e => panic!("not a snapshot {:?}", e),
+    }
+    assert!(matches!(&book[1][..], [MarketEvent::BookDelta(_)]));
+
+    let trades = parse_events(serde_json::from_str(&fixture_lines("kraken/trade.jsonl")[3]).unwrap()).unwrap();
+    assert_eq!(
+        trades[0],
+        MarketEvent::Trade(Trade {
+            instrument: Instrument::new(Venue::Kraken, "BTC", "USD", "XBT/USD"),
+            time: 1534614057321597000,
+            price: dec("5541.2"),
+            quantity: dec("0.15850568"),
+            side: Side::Sell,
+            trade_id: None,
+        })
+    );
+    assert_eq!(trades[1].time(), 1534614057324998000);
+
+    let spread = json!([0, ["5698.40000", "5700.00000", "1542057299.545897", "1.01234567", "0.98765432"], "spread", "XBT/USD"]);
+    assert_eq!(
+        parse_events(spread).unwrap(),
+        vec![MarketEvent::Spread(Spread {
+            instrument: Instrument::new(Venue::Kraken, "XBT", "USD", "XBT/USD"),
+            time: 1542057299545897000,
+            bid: dec("5698.4"),
+            bid_qty: dec("1.01234567"),
+            ask: dec("5700"),
+            ask_qty: dec("0.98765432"),
+        })]
+    );
+
+    let ohlc = json!([42, ["1542057314.748456", "1542057360.435743", "3586.70000", "3586.70000", "3586.60000", "3586.60000", "3586.68894", "0.03373000", 2], "ohlc-5", "XBT/USD"]);
+    assert_eq!(
+        parse_events(ohlc).unwrap(),
+        vec![MarketEvent::Candle(Candle {
+            instrument: Instrument::new(Venue::Kraken, "XBT", "USD", "XBT/USD"),
+            time: 1542057314748456000,
+            start: 1542057060435743000,
+            end: 1542057360435743000,
+            open: dec("3586.7"),
+            high: dec("3586.7"),
+            low: dec("3586.6"),
+            close: dec("3586.6"),
+            volume: dec("0.03373"),
+            trades: Some(2),
+            closed: false,
+        })]
+    );
+
+    let ticker = json!([0, {
+        "a": ["5525.40000", 1, "1.000"],
+        "b": ["5525.10000", 1, "1.000"],
+        "c": ["5525.10000", "0.00398963"],
+        "v": ["2634.11501494", "3591.17907851"]
+    }, "ticker", "XBT/USD"]);
+    match &parse_events(ticker).unwrap()[..] {
+        [MarketEvent::Ticker(t)] => {
+            assert_eq!((t.bid, t.ask), (dec("5525.1"), dec("5525.4")));
+            assert_eq!((t.last, t.volume_24h), (Some(dec("5525.1")), Some(dec("3591.17907851"))));
+        }
+        e => panic!("not a ticker {:?}", e),
+    }
+    assert!(parse_events(json!({"event": "heartbeat"})).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn every_platform_streams_events() {
+    let frames = fixture_lines("binance/depth.jsonl")
+        .into_iter()
+        .chain(fixture_lines("binance/trade.jsonl"))
+        .collect();
+    let (url, seen) = mock_ws_server(1, vec![r#"{"result":null,"id":1}"#.into()], frames).await;
+    let binance = BinancePlatform::new(&url, "http://unused");
+    assert_eq!(binance.venue(), Venue::Binance);
+    let events: Vec<MarketEvent> = binance
+        .start_event_stream(&["BNBBTC"])
+        .await
+        .unwrap()
+        .take(4)
+        .map(|e| e.unwrap())
+        .collect()
+        .await;
+    assert_eq!(seen.await.unwrap().uri, "/stream");
+    assert!(matches!(events[1], MarketEvent::BookDelta(_)));
+    assert!(matches!(events[3], MarketEvent::Trade(_)));
+    assert!(events.iter().all(|e| e.venue() == Venue::Binance));
+
+    let frames = fixture_lines("kraken/book.jsonl")
+        .into_iter()
+        .chain(fixture_lines("kraken/trade.jsonl"))
+        .collect();
+    let (url, seen) = mock_ws_server(2, vec![], frames).await;
+    let kraken = KrakenPlatform::new(&url);
+    assert_eq!(kraken.venue(), Venue::Kraken);
+    let events: Vec<MarketEvent> = kraken
+        .start_event_stream(&["XBT/USD"])
+        .await
+        .unwrap()
+        .take(5)
+        .map(|e| e.unwrap())
+        .collect()
+        .await;
+    let names: Vec<Value> = seen
+        .await
+        .unwrap()
+        .messages
+        .iter()
+        .map(|m| serde_json::from_str::<Value>(m).unwrap()["subscription"]["name"].clone())
+        .collect();
+    assert_eq!(names, vec![json!("book"), json!("trade")]);
+    assert!(matches!(events[0], MarketEvent::BookSnapshot(_)));
+    assert!(matches!(events[4], MarketEvent::Trade(_)));
+    assert!(events.iter().all(|e| e.instrument().base == "BTC"));
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::time::Duration;
+use tickstream::streams::merge::{merge_by_time, merge_ordered, Merged};
+use tokio::time::sleep;
+
+type Source = UnboundedSender<Result<(u64, &'static str)>>;
+
+fn time(item: &(u64, &'static str)) -> Option<u64> {
+    Some(item.0).filter(|t| *t > 0)
+}
+
+fn send(source: &Source, time: u64, what: &'static str) {
+    source.unbounded_send(Ok((time, what))).unwrap();
+}
+
+#[tokio::test]
+async fn orders_items_arriving_within_the_window() {
+    let (books, book_rx) = unbounded();
+    let (trades, trade_rx) = unbounded();
+    let merged = merge_ordered(vec![book_rx, trade_rx], time, Duration::from_millis(100));
+    tokio::pin!(merged);
+
+    send(&books, 20, "book");
+    send(&trades, 30, "trade");
+    sleep(Duration::from_millis(20)).await;
+    // the trade before the book arrives late, but within the window
+    send(&trades, 10, "trade");
+    send(&books, 30, "book");
+
+    let mut items = vec![];
+    for _ in 0..4 {
+        items.push(merged.next().await.unwrap().unwrap());
+    }
+    let order: Vec<(usize, u64)> = items.iter().map(|m| (m.source, m.item.0)).collect();
+    assert_eq!(order, vec![(1, 10), (0, 20), (1, 30), (0, 30)]);
+    assert!(items.iter().all(|m| m.late_by.is_none()));
+
+    drop(books);
+    drop(trades);
+    assert!(merged.next().await.is_none());
+}
+
+#[tokio::test]
+async fn reports_late_arrivals() {
+    let (books, book_rx) = unbounded();
+    let (trades, trade_rx) = unbounded();
+    let merged = merge_ordered(vec![book_rx, trade_rx], time, Duration::from_millis(20));
+    tokio::pin!(merged);
+
+    send(&books, 100, "book");
+    let first = merged.next().await.unwrap().unwrap();
+    assert_eq!(first.item, (100, "book"));
+
+    // after the book went out, too late to go before it
+    send(&trades, 60, "trade");
+    // items without a time go straight through
+    send(&trades, 0, "heartbeat");
+    send(&books, 110, "book");
+    assert_eq!(
+        merged.next().await.unwrap().unwrap(),
+        Merged {
+            source: 1,
+            item: (60, "trade"),
+            late_by: Some(40),
+        }
+    );
+    assert_eq!(merged.next().await.unwrap().unwrap().item, (0, "heartbeat"));
+    let next = merged.next().await.unwrap().unwrap();
+    assert_eq!((next.item, next.late_by), ((110, "book"), None));
+}
+
+#[tokio::test]
+async fn passes_errors_on_and_flushes_at_the_end() {
+    let (books, book_rx) = unbounded();
+    let (trades, trade_rx) = unbounded();
+    let merged = merge_ordered(vec![book_rx, trade_rx], time, Duration::from_secs(60));
+    tokio::pin!(merged);
+
+    send(&books, 20, "book");
+    trades.unbounded_send(Err(anyhow!("disconnected"))).unwrap();
+    send(&trades, 10, "trade");
+    let err = merged.next().await.unwrap().err().unwrap();
+    assert!(format!("{:#}", err).contains("disconnected"));
+
+    // held for a minute, unless the streams end
+    drop(books);
+    drop(trades);
+    let rest: Vec<u64> = merged.map_ok(|m| m.item.0).try_collect().await.unwrap();
+    assert_eq!(rest, vec![10, 20]);
+}
+
+#[tokio::test]
+async fn merges_ordered_streams() {
+    let a = stream::iter(vec![Ok((1, "a")), Ok((4, "a")), Ok((5, "a"))]);
+    let b = stream::iter(vec![Ok((2, "b")), Ok((4, "b")), Ok((9, "b"))]);
+    let merged: Vec<(u64, &str)> = merge_by_time(vec![a, b], time).try_collect().await.unwrap();
+    assert_eq!(merged, vec![(1, "a"), (2, "b"), (4, "a"), (4, "b"), (5, "a"), (9, "b")]);
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use common::{remove, temp_path, trade};
+use futures::stream::{StreamExt, TryStreamExt};
+use rust_decimal::Decimal;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tickstream::streams::file::MixedFileSink2;
+use tickstream::market::{MarketEvent, Venue};
+use tickstream::streams::replay::{Pace, Replayer};
+use tickstream::streams::Item2;
+use tickstream::{BookUpdate, Platform, Trade};
+
+fn book(symbol: &str, time: u64) -> BookUpdate {
+    BookUpdate {
+        event: "depthUpdate".into(),
+        event_time: time,
+        symbol: symbol.into(),
+        first_update_id: time,
+        last_update_id: time,
+        bids: vec![(Decimal::new(100, 0), 1.into())],
+        asks: vec![(Decimal::new(101, 0), 1.into())],
+    }
+}
+
+async fn record(path: &Path, items: Vec<Item2<BookUpdate, Trade>>) {
+    let mut sink = MixedFileSink2::<BookUpdate, Trade>::create(path, "test", 2).await.unwrap();
+    for item in items {
+        match item {
+            Item2::A(b) => sink.send_a(b).await.unwrap(),
+            Item2::B(t) => sink.send_b(t).await.unwrap(),
+        }
+    }
+    sink.flush().await.unwrap();
+}
+
+#[tokio::test]
+async fn merges_files_in_time_order() {
+    let btc = temp_path("replay-btc");
+    let eth = temp_path("replay-eth");
+    record(
+        &btc,
+        vec![
+            Item2::A(book("BTCUSDT", 10)),
+            Item2::B(trade("BTCUSDT", 20)),
+            Item2::A(book("BTCUSDT", 40)),
+        ],
+    )
+    .await;
+    record(
+        &eth,
+        vec![
+            Item2::B(trade("ETHUSDT", 5)),
+            Item2::A(book("ETHUSDT", 30)),
+            Item2::B(trade("ETHUSDT", 40)),
+        ],
+    )
+    .await;
+    let replayer = Replayer::open(Venue::Binance, &[&btc, &eth])
+        .await
+        .unwrap()
+        .pace(Pace::Unpaced)
+        .unwrap();
+
+    let times: Vec<u64> = replayer
+        .replay()
+        .await
+        .unwrap()
+        .map_ok(|item| match item {
+            Item2::A(b) => b.event_time,
+            Item2::B(t) => t.event_time,
+        })
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(times, vec![5, 10, 20, 30, 40, 40]);
+
+    let books: Vec<BookUpdate> = replayer.start_book_stream(&[]).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(books, vec![book("BTCUSDT", 10), book("ETHUSDT", 30), book("BTCUSDT", 40)]);
+    let trades: Vec<Trade> = replayer.start_trade_stream(&["ethusdt"]).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(trades, vec![trade("ETHUSDT", 5), trade("ETHUSDT", 40)]);
+
+    let later: Vec<BookUpdate> = Replayer::open(Venue::Binance, &[&btc, &eth])
+        .await
+        .unwrap()
+        .pace(Pace::Unpaced)
+        .unwrap()
+        .starting_at(25)
+        .start_book_stream(&["btcusdt", "ethusdt"])
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(later, vec![book("ETHUSDT", 30), book("BTCUSDT", 40)]);
+    remove(&btc);
+    remove(&eth);
+}
+
+#[tokio::test]
+async fn puts_recordings_back_in_time_order() {
+    // in arrival order, as recorded from connections of different latency
+    let path = temp_path("replay-late");
+    let arrivals = [1000, 1300, 1100, 1600, 1400, 1500, 5000, 6000, 2000];
+    record(&path, arrivals.iter().map(|&t| Item2::B(trade("BTCUSDT", t))).collect()).await;
+    let replayer = Replayer::open(Venue::Binance, &[&path])
+        .await
+        .unwrap()
+        .pace(Pace::Unpaced)
+        .unwrap()
+        .reorder_window(Duration::from_millis(500));
+
+    let times: Vec<u64> = replayer
+        .replay()
+        .await
+        .unwrap()
+        .map_ok(|item| match item {
+            Item2::A(b) => b.event_time,
+            Item2::B(t) => t.event_time,
+        })
+        .try_collect()
+        .await
+        .unwrap();
+    // the last one is further out of order than the window
+    assert_eq!(times, vec![1000, 1100, 1300, 1400, 1500, 1600, 5000, 2000, 6000]);
+    remove(&path);
+}
+
+/// How long replaying 5 trades 100ms apart takes at `pace`
+async fn replay_time(path: &Path, pace: Pace) -> Duration {
+    let replayer = Replayer::open(Venue::Binance, &[path]).await.unwrap().pace(pace).unwrap();
+    let mut trades = replayer.start_trade_stream(&["btcusdt"]).await.unwrap();
+    let start = Instant::now();
+    let mut n = 0;
+    while let Some(trade) = trades.next().await {
+        trade.unwrap();
+        n += 1;
+    }
+    assert_eq!(n, 5);
+    start.elapsed()
+}
+
+#[tokio::test]
+async fn paces_by_event_time() {
+    let path = temp_path("replay-pace");
+    record(&path, (0..5).map(|i| Item2::B(trade("BTCUSDT", 1000 + i * 100))).collect()).await;
+
+    let real = replay_time(&path, Pace::RealTime).await;
+    assert!(real >= Duration::from_millis(400), "{:?}", real);
+    let fast = replay_time(&path, Pace::Accelerated(10.0)).await;
+    assert!(fast >= Duration::from_millis(40) && fast < real, "{:?}", fast);
+    let unpaced = replay_time(&path, Pace::Unpaced).await;
+    assert!(unpaced < Duration::from_millis(40), "{:?}", unpaced);
+    remove(&path);
+}
+
+#[tokio::test]
+async fn streams_share_a_clock() {
+    let path = temp_path("replay-clock");
+    record(
+        &path,
+        vec![Item2::A(book("BTCUSDT", 1000)), Item2::B(trade("BTCUSDT", 1200))],
+    )
+    .await;
+    let replayer = Replayer::open(Venue::Binance, &[&path])
+        .await
+        .unwrap()
+        .pace(Pace::Accelerated(2.0))
+        .unwrap();
+    let mut books = replayer.start_book_stream(&[]).await.unwrap();
+    let mut trades = replayer.start_trade_stream(&[]).await.unwrap();
+
+    let start = Instant::now();
+    books.next().await.unwrap().unwrap();
+    trades.next().await.unwrap().unwrap();
+    // the trade is due 200ms after the book at half the time
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(200), "{:?}", elapsed);
+    remove(&path);
+}
+
+#[tokio::test]
+async fn only_positive_speeds() {
+    let path = temp_path("replay-speeds");
+    record(&path, vec![Item2::B(trade("BTCUSDT", 1000))]).await;
+    for speed in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+        let replayer = Replayer::open(Venue::Binance, &[&path]).await.unwrap();
+        let err = replayer.pace(Pace::Accelerated(speed)).err().unwrap();
+        assert!(err.to_string().contains("times the recorded pace"), "{}", err);
+    }
+    assert!(Replayer::open(Venue::Binance, &[&path])
+        .await
+        .unwrap()
+        .pace(Pace::Accelerated(0.5))
+        .is_ok());
+    remove(&path);
+}
+
+#[tokio::test]
+async fn replays_as_a_platform() {
+    let path = temp_path("replay-platform");
+    record(
+        &path,
+        vec![Item2::A(book("BTCUSDT", 10)), Item2::B(trade("BTCUSDT", 20))],
+    )
+    .await;
+    let platforms: Vec<Box<dyn Platform>> = vec![Box::new(
+        Replayer::open(Venue::Binance, &[&path]).await.unwrap().pace(Pace::Unpaced).unwrap(),
+    )];
+    let replayer = &platforms[0];
+    assert_eq!(replayer.venue(), Venue::Binance);
+
+    let trades: Vec<Trade> = replayer.start_trade_stream(&["btcusdt"]).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(trades, vec![trade("BTCUSDT", 20)]);
+    let events: Vec<MarketEvent> = replayer.start_event_stream(&["btcusdt"]).await.unwrap().try_collect().await.unwrap();
+    match &events[..] {
+        [MarketEvent::BookDelta(b), MarketEvent::Trade(t)] => {
+            assert_eq!((b.time, b.last_update_id), (10000000, Some(10)));
+            assert_eq!((t.time, t.instrument.to_string().as_str()), (20000000, "BTC/USDT@binance"));
+        }
+        e => panic!("not a book and a trade {:?}", e),
+    }
+    assert!(replayer.start_synced_book_stream("btcusdt").await.is_err());
+    remove(&path);
+}
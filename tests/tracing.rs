@@ -0,0 +1,150 @@
+mod common;
+
+use common::mock_ws_sessions;
+use futures::StreamExt;
+use futures_util::pin_mut;
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tickstream::streams::websockets::{subscribe, SubscriptionProtocol, WsConfig, WsEvent};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Records every event and span field as `name: field=value ..`
+#[derive(Clone, Default)]
+struct Capture {
+    lines: Arc<Mutex<Vec<String>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+struct Line(String);
+
+impl Visit for Line {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+impl Capture {
+    fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut line = Line(format!("span {}:", span.metadata().name()));
+        span.record(&mut line);
+        self.lines.lock().unwrap().push(line.0);
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _: &Id, values: &Record<'_>) {
+        let mut line = Line("record:".into());
+        values.record(&mut line);
+        self.lines.lock().unwrap().push(line.0);
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = Line(format!("{}:", event.metadata().level()));
+        event.record(&mut line);
+        self.lines.lock().unwrap().push(line.0);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+struct TestProtocol(String);
+
+impl SubscriptionProtocol for TestProtocol {
+    fn url(&self, _topics: &[String]) -> String {
+        self.0.clone()
+    }
+
+    fn subscribe(&self, _topics: &[String]) -> anyhow::Result<Vec<String>> {
+        Ok(vec!["{}".into()])
+    }
+
+    fn unsubscribe(&self, _topics: &[String]) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+#[tokio::test]
+async fn subscriptions_trace_connections_and_counts() {
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(capture.clone());
+
+    let (url, _seen) = mock_ws_sessions::<String>(
+        1,
+        vec![vec![r#"{"n":1}"#.into(), r#"{"n":2}"#.into()], vec!["oops".into(), r#"{"n":3}"#.into()]],
+    )
+    .await;
+    let config = WsConfig {
+        backoff: backoff::ExponentialBackoff {
+            initial_interval: Duration::from_millis(10),
+            max_elapsed_time: Some(Duration::from_millis(500)),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let (_, s) = subscribe(TestProtocol(url), vec!["a".into()], config, |v: Value| {
+        anyhow::Result::<_>::Ok(Some(v))
+    })
+    .await
+    .unwrap();
+    pin_mut!(s);
+    let events: Vec<_> = s.take(5).map(|e| e.unwrap()).collect().await;
+    assert_eq!(events[3], WsEvent::Reconnected);
+    assert_eq!(events[4], WsEvent::Data(serde_json::json!({"n": 3})));
+
+    let lines = capture.lines();
+    let has = |needle: &str| lines.iter().any(|l| l.contains(needle));
+    assert!(has("span ws_subscription:"), "{:#?}", lines);
+    assert!(has("span ws_connection:"), "{:#?}", lines);
+    assert!(has("record: messages=2"), "{:#?}", lines);
+    assert!(has("WARN: message=disconnected messages=2"), "{:#?}", lines);
+    assert!(has("record: reconnects=1"), "{:#?}", lines);
+    assert!(has("INFO: message=reconnected reconnects=1"), "{:#?}", lines);
+    assert!(has("record: decode_failures=1"), "{:#?}", lines);
+    assert!(has("WARN: message=failed to decode frame"), "{:#?}", lines);
+}
+
+#[tokio::test]
+async fn decode_failures_are_counted_per_connection() {
+    let capture = Capture::default();
+    let _guard = tracing::subscriber::set_default(capture.clone());
+
+    let (url, _seen) = mock_ws_sessions::<String>(
+        1,
+        vec![vec!["oops".into(), "{".into(), r#"{"n":1}"#.into()]],
+    )
+    .await;
+    let (_, s) = subscribe(TestProtocol(url), vec!["a".into()], WsConfig::default(), |v: Value| {
+        anyhow::Result::<_>::Ok(Some(v))
+    })
+    .await
+    .unwrap();
+    pin_mut!(s);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(serde_json::json!({"n": 1})));
+
+    let lines = capture.lines();
+    let failures: Vec<&String> = lines.iter().filter(|l| l.contains("decode_failures=")).collect();
+    assert_eq!(failures, vec!["record: decode_failures=1", "record: decode_failures=2"], "{:#?}", lines);
+    assert!(lines.iter().any(|l| l.contains("record: messages=3")), "{:#?}", lines);
+
+    // still subscribed
+    let next = tokio::time::timeout(Duration::from_millis(50), s.next()).await;
+    assert!(next.is_err(), "{:?}", next);
+}
@@ -0,0 +1,284 @@
+mod common;
+
+use backoff::ExponentialBackoff;
+use common::mock_ws_sessions;
+use futures::{SinkExt, StreamExt};
+use futures_util::pin_mut;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tickstream::streams::websockets::{subscribe, SubscriptionProtocol, WsConfig, WsEvent};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+fn fast_config() -> WsConfig {
+    WsConfig {
+        backoff: ExponentialBackoff {
+            initial_interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(50),
+            max_elapsed_time: Some(Duration::from_millis(500)),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// Subscribes with `{"sub": topics}` and unsubscribes with
+/// `{"unsub": topics}` at a fixed url
+struct TestProtocol(String);
+
+impl SubscriptionProtocol for TestProtocol {
+    fn url(&self, _topics: &[String]) -> String {
+        self.0.clone()
+    }
+
+    fn subscribe(&self, topics: &[String]) -> anyhow::Result<Vec<String>> {
+        Ok(vec![json!({ "sub": topics }).to_string()])
+    }
+
+    fn unsubscribe(&self, topics: &[String]) -> anyhow::Result<Vec<String>> {
+        Ok(vec![json!({ "unsub": topics }).to_string()])
+    }
+}
+
+fn topics(t: &[&str]) -> Vec<String> {
+    t.iter().map(|t| t.to_string()).collect()
+}
+
+fn identity(v: Value) -> anyhow::Result<Option<Value>> {
+    Ok(Some(v))
+}
+
+#[tokio::test]
+async fn reconnects_and_resubscribes_after_read_errors() {
+    let (url, mut seen) = mock_ws_sessions::<String>(
+        1,
+        vec![
+            vec![r#"{"n":1}"#.into(), r#"{"n":2}"#.into()],
+            vec![r#"{"n":3}"#.into()],
+        ],
+    )
+    .await;
+
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), identity)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    let events: Vec<WsEvent<Value>> = s.take(5).map(|e| e.unwrap()).collect().await;
+
+    assert_eq!(
+        events,
+        vec![
+            WsEvent::Data(json!({"n": 1})),
+            WsEvent::Data(json!({"n": 2})),
+            WsEvent::Gap,
+            WsEvent::Reconnected,
+            WsEvent::Data(json!({"n": 3})),
+        ]
+    );
+    for _ in 0..2 {
+        assert_eq!(seen.recv().await.unwrap().messages, vec![r#"{"sub":["a"]}"#]);
+    }
+}
+
+#[tokio::test]
+async fn skips_frames_that_fail_to_decode_or_translate() {
+    let (url, _seen) = mock_ws_sessions::<String>(
+        1,
+        vec![vec![r#"{"n":1}"#.into(), "oops".into(), r#"{"n":-1}"#.into(), r#"{"n":2}"#.into()]],
+    )
+    .await;
+
+    let positive = |v: Value| match v["n"].as_i64() {
+        Some(n) if n < 0 => Err(anyhow::anyhow!("negative")),
+        _ => Ok(Some(v)),
+    };
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), positive)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    let events: Vec<WsEvent<Value>> = s.take(2).map(|e| e.unwrap()).collect().await;
+    assert_eq!(events, vec![WsEvent::Data(json!({"n": 1})), WsEvent::Data(json!({"n": 2}))]);
+}
+
+#[tokio::test]
+async fn fails_once_the_backoff_gives_up() {
+    // grab a free port and leave nothing listening on it
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let url = format!("ws://127.0.0.1:{}", port);
+
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), identity)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    let err = s.next().await.unwrap().unwrap_err();
+    assert!(err.to_string().contains("failed to connect"));
+    assert!(s.next().await.is_none());
+}
+
+#[tokio::test]
+async fn answers_pings() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        ws.next().await.unwrap().unwrap();
+        ws.send(Message::Ping(b"hb".to_vec())).await.unwrap();
+        ws.send(Message::Text(r#"{"n":1}"#.into())).await.unwrap();
+        ws.send(Message::Pong(vec![])).await.unwrap();
+        ws.send(Message::Text(r#"{"n":2}"#.into())).await.unwrap();
+        let mut pongs = vec![];
+        while let Some(Ok(m)) = ws.next().await {
+            if m.is_pong() {
+                pongs.push(m);
+            }
+        }
+        pongs
+    });
+
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), identity)
+        .await
+        .unwrap();
+    let mut s = Box::pin(s);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 1})));
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 2})));
+    drop(s);
+    assert_eq!(server.await.unwrap(), vec![Message::Pong(b"hb".to_vec())]);
+}
+
+#[tokio::test]
+async fn reconnects_when_closed() {
+    let (url, mut seen) = mock_ws_sessions::<Message>(
+        1,
+        vec![
+            vec![Message::Text(r#"{"n":1}"#.into()), Message::Close(None)],
+            vec![Message::Text(r#"{"n":2}"#.into())],
+        ],
+    )
+    .await;
+
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), identity)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    let events: Vec<WsEvent<Value>> = s.take(4).map(|e| e.unwrap()).collect().await;
+
+    assert_eq!(
+        events,
+        vec![
+            WsEvent::Data(json!({"n": 1})),
+            WsEvent::Gap,
+            WsEvent::Reconnected,
+            WsEvent::Data(json!({"n": 2})),
+        ]
+    );
+    assert!(seen.recv().await.is_some());
+    assert!(seen.recv().await.is_some());
+}
+
+#[tokio::test]
+async fn reconnects_when_idle() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut idle = vec![];
+        for n in 1..=2 {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.next().await.unwrap().unwrap();
+            ws.send(Message::Text(format!(r#"{{"n":{}}}"#, n))).await.unwrap();
+            // go quiet without closing
+            idle.push(ws);
+        }
+        futures::future::pending::<()>().await;
+    });
+
+    let config = WsConfig {
+        idle_timeout: Some(Duration::from_millis(100)),
+        ..fast_config()
+    };
+    let (_, s) = subscribe(TestProtocol(url), topics(&["a"]), config, identity)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    let events: Vec<WsEvent<Value>> = s.take(5).map(|e| e.unwrap()).collect().await;
+
+    assert_eq!(
+        events,
+        vec![
+            WsEvent::Data(json!({"n": 1})),
+            WsEvent::Gap,
+            WsEvent::Reconnected,
+            WsEvent::Data(json!({"n": 2})),
+            WsEvent::Gap,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn handle_changes_topics_at_runtime() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        let mut messages = vec![];
+        messages.push(ws.next().await.unwrap().unwrap());
+        ws.send(Message::Text(r#"{"n":1}"#.into())).await.unwrap();
+        messages.push(ws.next().await.unwrap().unwrap());
+        messages.push(ws.next().await.unwrap().unwrap());
+        ws.send(Message::Text(r#"{"n":2}"#.into())).await.unwrap();
+        while let Some(Ok(_)) = ws.next().await {}
+        messages
+    });
+
+    let (handle, s) = subscribe(TestProtocol(url), topics(&["a"]), fast_config(), identity)
+        .await
+        .unwrap();
+    let mut s = Box::pin(s);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 1})));
+    handle.subscribe(&["a", "b"]).unwrap();
+    handle.unsubscribe(&["a", "c"]).unwrap();
+    assert_eq!(handle.topics(), topics(&["b"]));
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 2})));
+    drop(s);
+
+    assert_eq!(
+        server.await.unwrap(),
+        vec![
+            Message::Text(r#"{"sub":["a"]}"#.into()),
+            Message::Text(r#"{"sub":["b"]}"#.into()),
+            Message::Text(r#"{"unsub":["a"]}"#.into()),
+        ]
+    );
+    assert!(handle.subscribe(&["d"]).is_err());
+}
+
+#[tokio::test]
+async fn replays_active_topics_after_reconnect() {
+    let (url, mut seen) = mock_ws_sessions::<String>(
+        1,
+        vec![vec![r#"{"n":1}"#.into()], vec![r#"{"n":2}"#.into()]],
+    )
+    .await;
+
+    let (handle, s) = subscribe(TestProtocol(url), topics(&["a", "b"]), fast_config(), identity)
+        .await
+        .unwrap();
+    pin_mut!(s);
+    assert_eq!(s.next().await.unwrap().unwrap(), WsEvent::Data(json!({"n": 1})));
+    handle.subscribe(&["c"]).unwrap();
+    handle.unsubscribe(&["a"]).unwrap();
+    let events: Vec<WsEvent<Value>> = s.take(3).map(|e| e.unwrap()).collect().await;
+
+    assert_eq!(
+        events,
+        vec![WsEvent::Gap, WsEvent::Reconnected, WsEvent::Data(json!({"n": 2}))]
+    );
+    assert_eq!(seen.recv().await.unwrap().messages, vec![r#"{"sub":["a","b"]}"#]);
+    assert_eq!(seen.recv().await.unwrap().messages, vec![r#"{"sub":["b","c"]}"#]);
+}
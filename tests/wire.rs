@@ -0,0 +1,219 @@
+//! Payloads of every vendor type, in the shapes the venues document and the
+//! ones they actually send where those differ, so that a venue changing its
+//! messages, or a mapping that doesn't match them, fails here first.
+mod common;
+
+use common::{dec, fixture, fixture_lines};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use tickstream::binance_ws::{
+    AggregateTrade, Book, BookDepthUpdate, BookTicker, KlineUpdate, StreamMessage, StreamRequest, Trade,
+};
+use tickstream::kraken_rest::{
+    self, Asset, AssetPair, OHLCResponse, Orders, Response, SpreadResponse, Subscribe, Subscription,
+    SubscriptionName, TickerPair, Time, TradeResponse, TradeSide, TradeType,
+};
+
+/// The `data` of each frame of a recorded combined stream
+fn data(name: &str) -> Vec<Value> {
+    fixture_lines(name)
+        .iter()
+        .map(|l| serde_json::from_str::<StreamMessage<Value>>(l).unwrap().data.unwrap())
+        .collect()
+}
+
+/// Keys of `payload` that `T` doesn't map, as paths like `k.B`, after
+/// checking every key it does map round trips to the same value
+fn unmapped<T: DeserializeOwned + Serialize>(payload: &Value) -> BTreeSet<String> {
+    let parsed: T = serde_json::from_value(payload.clone()).unwrap();
+    let mut keys = BTreeSet::new();
+    compare("", payload, &serde_json::to_value(&parsed).unwrap(), &mut keys);
+    keys
+}
+
+fn compare(path: &str, payload: &Value, written: &Value, unmapped: &mut BTreeSet<String>) {
+    match (payload, written) {
+        (Value::Object(payload), Value::Object(written)) => {
+            for (key, value) in payload {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match written.get(key) {
+                    Some(written) => compare(&path, value, written, unmapped),
+                    None => {
+                        unmapped.insert(path);
+                    }
+                }
+            }
+        }
+        _ => assert_eq!(payload, written, "field {}", path),
+    }
+}
+
+fn keys(keys: &[&str]) -> BTreeSet<String> {
+    keys.iter().map(|k| k.to_string()).collect()
+}
+
+fn kraken<T: DeserializeOwned>(name: &str) -> T {
+    serde_json::from_str::<Response<T>>(&fixture(name))
+        .unwrap()
+        .into_result()
+        .unwrap()
+}
+
+#[test]
+fn binance_trade() {
+    let trades = data("binance/trade.jsonl");
+    for t in &trades {
+        assert_eq!(unmapped::<Trade>(t), keys(&[]));
+    }
+    let t: Trade = serde_json::from_value(trades[0].clone()).unwrap();
+    assert_eq!((t.symbol.as_str(), t.trade_id), ("BNBBTC", 12345));
+    assert_eq!((t.buyer, t.seller), (Some(88), Some(50)));
+    assert_eq!((t.price, t.quantity), (dec("0.001"), dec("100")));
+    assert_eq!((t.event_time, t.trade_time), (1614556800456, 1614556800450));
+    assert!(t.maker);
+
+    // order ids were dropped from the stream in December 2023
+    for t in data("binance/trade_no_order_ids.jsonl") {
+        assert_eq!(unmapped::<Trade>(&t), keys(&[]));
+        let t: Trade = serde_json::from_value(t).unwrap();
+        assert_eq!((t.buyer, t.seller), (None, None));
+    }
+}
+
+#[test]
+fn binance_agg_trade() {
+    let trades = data("binance/agg_trade.jsonl");
+    for t in &trades {
+        assert_eq!(unmapped::<AggregateTrade>(t), keys(&[]));
+    }
+    let t: AggregateTrade = serde_json::from_value(trades[0].clone()).unwrap();
+    assert_eq!((t.trade_id, t.first_trade, t.last_trade), (12345, 100, 105));
+}
+
+#[test]
+fn binance_book_ticker() {
+    let tickers = data("binance/book_ticker.jsonl");
+    for t in &tickers {
+        assert_eq!(unmapped::<BookTicker>(t), keys(&[]));
+    }
+    let t: BookTicker = serde_json::from_value(tickers[0].clone()).unwrap();
+    // update ids outgrew u32
+    assert_eq!(t.update_id, 56430235497);
+    assert_eq!((t.best_bid, t.best_ask_qty), (dec("25.3519"), dec("40.66")));
+}
+
+#[test]
+fn binance_depth() {
+    for u in data("binance/depth.jsonl") {
+        assert_eq!(unmapped::<BookDepthUpdate>(&u), keys(&[]));
+    }
+    let snapshot: Value = serde_json::from_str(&fixture("binance/depth_snapshot_160.json")).unwrap();
+    assert_eq!(unmapped::<Book>(&snapshot), keys(&[]));
+    let book: Book = serde_json::from_value(snapshot).unwrap();
+    assert_eq!(book.last_update_id, 160);
+    assert_eq!(book.bids[0], (dec("0.0024"), dec("14")));
+}
+
+#[test]
+fn binance_kline() {
+    let klines = data("binance/kline.jsonl");
+    for k in &klines {
+        // the symbol is repeated and "B" is unused
+        assert_eq!(unmapped::<KlineUpdate>(k), keys(&["k.B", "k.s"]));
+    }
+    let k: KlineUpdate = serde_json::from_value(klines[1].clone()).unwrap();
+    assert_eq!((k.kline.first_trade_id, k.kline.last_trade_id), (100, 210));
+    assert!(k.kline.closed);
+}
+
+#[test]
+fn binance_stream_messages() {
+    let reply: StreamMessage<Value> = serde_json::from_str(r#"{"result":null,"id":1}"#).unwrap();
+    assert_eq!((reply.stream, reply.data, reply.id), (None, None, Some(1)));
+
+    let request = StreamRequest::subscribe(vec!["btcusdt@aggTrade".into(), "btcusdt@depth".into()]);
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        json!({"method": "SUBSCRIBE", "params": ["btcusdt@aggTrade", "btcusdt@depth"], "id": request.id})
+    );
+}
+
+#[test]
+fn kraken_reference_data() {
+    let time: Time = kraken("kraken/rest/time.json");
+    assert_eq!(time.rfc1123, "Sun, 21 Mar 21 14:23:14 +0000");
+
+    let assets: BTreeMap<String, Asset> = kraken("kraken/rest/assets.json");
+    let xbt = &assets["XXBT"];
+    assert_eq!((xbt.aclass.as_str(), xbt.decimals, xbt.display_decimals), ("currency", 10, 5));
+
+    let pairs: BTreeMap<String, AssetPair> = kraken("kraken/rest/asset_pairs.json");
+    let pair = &pairs["XXBTZUSD"];
+    assert_eq!((pair.base.as_str(), pair.quote.as_str()), ("XXBT", "ZUSD"));
+    assert_eq!((pair.fees[1].volume, pair.fees[1].percent), (50000.0, 0.24));
+    assert_eq!(pair.fees_maker.as_ref().map(Vec::len), Some(3));
+    assert_eq!(pair.leverage_buy, vec![2.0, 3.0, 4.0, 5.0]);
+    assert_eq!((pair.margin_call, pair.margin_stop), (80, 40));
+}
+
+#[test]
+fn kraken_market_data() {
+    let tickers: BTreeMap<String, TickerPair> = kraken("kraken/rest/ticker.json");
+    let t = &tickers["XXBTZUSD"];
+    assert_eq!((t.a.price, t.a.whole_lot_volume, t.a.lot_volume), (dec("57176.4"), dec("1"), dec("1")));
+    assert_eq!((t.c.price, t.c.volume), (dec("57176.4"), dec("0.0011501")));
+    assert_eq!((t.p.today, t.l.last24, t.h.today), (dec("57438.97049"), dec("56525"), dec("58121.2")));
+    assert_eq!(t.o, dec("57802.6"));
+
+    let depth: BTreeMap<String, Orders> = kraken("kraken/rest/depth.json");
+    let ask = &depth["XXBTZUSD"].asks[1];
+    assert_eq!((ask.price, ask.volume, ask.timestamp), (dec("57180"), dec("0.25"), 1616336590));
+
+    let ohlc: OHLCResponse = kraken("kraken/rest/ohlc.json");
+    let c = &ohlc.data["XXBTZUSD"][0];
+    assert_eq!((c.time, c.open, c.low, c.vwap, c.count), (1616336520, dec("57150"), dec("57140"), dec("57160.2"), 27));
+
+    let trades: TradeResponse = kraken("kraken/rest/trades.json");
+    let t: &kraken_rest::Trade = &trades.data["XXBTZUSD"][0];
+    assert_eq!((t.price, t.time), (dec("57176.4"), dec("1616336594.1234")));
+    assert!(matches!((t.side, t.type_), (TradeSide::Buy, TradeType::Market)));
+    assert_eq!(t.miscellaneous, "");
+    assert_eq!(trades.last, 1616336595432100000);
+
+    let spread: SpreadResponse = kraken("kraken/rest/spread.json");
+    let s = &spread.data["XXBTZUSD"][1];
+    assert_eq!((s.time, s.bid, s.ask), (1616336595, dec("57176.2"), dec("57176.4")));
+}
+
+#[test]
+fn kraken_subscriptions() {
+    let mut subscription = Subscription::new(SubscriptionName::Ohlc);
+    subscription.interval = Some(5);
+    let subscribe = Subscribe {
+        event: "subscribe".into(),
+        pair: vec!["XBT/USD".into()],
+        subscription,
+    };
+    assert_eq!(
+        serde_json::to_value(&subscribe).unwrap(),
+        json!({"event": "subscribe", "pair": ["XBT/USD"], "subscription": {"name": "ohlc", "interval": 5}})
+    );
+    let names: Vec<Value> = [
+        SubscriptionName::Book,
+        SubscriptionName::OpenOrders,
+        SubscriptionName::OwnTrades,
+        SubscriptionName::Spread,
+        SubscriptionName::Ticker,
+        SubscriptionName::Trade,
+        SubscriptionName::All,
+    ]
+    .iter()
+    .map(|n| serde_json::to_value(n).unwrap())
+    .collect();
+    assert_eq!(
+        names,
+        vec![json!("book"), json!("openOrders"), json!("ownTrades"), json!("spread"), json!("ticker"), json!("trade"), json!("*")]
+    );
+}